@@ -0,0 +1,109 @@
+//! Writing destination files atomically, through a temporary
+//! file in the same directory renamed over the destination
+
+use std::{
+    fs::{self, File, OpenOptions},
+    io::ErrorKind,
+    path::Path,
+};
+
+use anyhow::Context;
+use log::warn;
+
+use crate::config::ROOT_CONFIG;
+
+/// Prefix of the temporary files written before they replace their destination
+const ATOMIC_WRITE_PREFIX: &str = ".typewriter-";
+
+/// Writes the destination with the provided function, so the destination
+/// never has partially written content if the process is interrupted.
+///
+/// With atomic_write the content is written to a temporary file next to the
+/// destination first, which is then renamed over it, otherwise the
+/// destination is truncated and written to directly.
+pub fn write_destination<T>(
+    destination: &Path,
+    write: impl FnOnce(&mut File) -> anyhow::Result<T>,
+) -> anyhow::Result<T> {
+    if !ROOT_CONFIG.get_config().apply.atomic_write {
+        let mut destination_file = OpenOptions::new()
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .open(destination)
+            .with_context(|| format!("While trying to open file {:?} to write to", destination))?;
+
+        return write(&mut destination_file);
+    }
+
+    // Symlinked destinations are written through to their target,
+    // renaming over the link itself would replace it with a file
+    let resolved_destination = if destination.is_symlink() {
+        fs::canonicalize(destination).with_context(|| {
+            format!(
+                "While trying to resolve the target of symlinked destination {:?}",
+                destination
+            )
+        })?
+    } else {
+        destination.to_path_buf()
+    };
+    let destination = resolved_destination.as_path();
+
+    let destination_dir = destination
+        .parent()
+        .with_context(|| format!("Destination {:?} has no parent directory", destination))?;
+
+    let mut temp_file = tempfile::Builder::new()
+        .prefix(ATOMIC_WRITE_PREFIX)
+        .tempfile_in(destination_dir)
+        .with_context(|| {
+            format!(
+                "While trying to create temporary file in {:?} to write {:?} through",
+                destination_dir, destination
+            )
+        })?;
+
+    let result = write(temp_file.as_file_mut())?;
+
+    // Make sure all of the content made it to disk before replacing anything
+    temp_file.as_file().sync_all().with_context(|| {
+        format!(
+            "While trying to flush temporary file {:?} for {:?}",
+            temp_file.path(),
+            destination
+        )
+    })?;
+
+    // Keep the permissions of the destination being replaced
+    if let Ok(metadata) = fs::metadata(destination) {
+        fs::set_permissions(temp_file.path(), metadata.permissions())?;
+    }
+
+    match temp_file.persist(destination) {
+        Ok(_) => Ok(result),
+        // Renaming only works within a filesystem, e.g bind mounted
+        // destinations have to be copied over instead
+        Err(e) if e.error.kind() == ErrorKind::CrossesDevices => {
+            warn!(
+                "Could not atomically replace {:?} since its temporary file is on another filesystem, copying over it instead",
+                destination
+            );
+
+            fs::copy(e.file.path(), destination).with_context(|| {
+                format!(
+                    "While trying to copy temporary file {:?} over {:?}",
+                    e.file.path(),
+                    destination
+                )
+            })?;
+            Ok(result)
+        }
+        Err(e) => Err(e.error).with_context(|| {
+            format!(
+                "While trying to rename temporary file over {:?}",
+                destination
+            )
+        }),
+    }
+}
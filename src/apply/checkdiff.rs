@@ -3,20 +3,22 @@
 //! (atomicity in all)
 
 use std::{
+    borrow::Cow,
     collections::HashMap,
     fs::{self, File},
     io::{BufReader, Read},
     path::PathBuf,
+    time::UNIX_EPOCH,
 };
 
 use anyhow::{Context, bail};
 use inquire::Confirm;
-use log::info;
+use log::{info, warn};
 use serde::{Deserialize, Serialize};
-use xxhash_rust::xxh3::Xxh3;
+use xxhash_rust::xxh3::{Xxh3, xxh3_64};
 
 use crate::{
-    apply::strategy::ApplyStrategy,
+    apply::{file_metadata_dir, hooks::HookStrategy, metadata_dirs, strategy::ApplyStrategy},
     cleanpath::CleanPath,
     config::ROOT_CONFIG,
     file::{TrackedFile, TrackedFileList},
@@ -26,21 +28,44 @@ use crate::{
 /// This stage will prompt the user whether or not
 /// to continue with the apply if the files are found to
 /// be different.
-#[derive(Deserialize, Debug)]
+#[derive(Deserialize, Serialize, Debug)]
 pub enum FileCheckDiffStrategy {
     // Checks by using XXHash for diff
     #[serde(rename = "xxhash")]
     XXHashDiff,
 
+    // Checks by using Blake3 for diff, slower
+    // but resistant to collisions
+    #[serde(rename = "blake3")]
+    Blake3Diff,
+
+    // Checks by comparing the modification time of the
+    // destination, which avoids reading it at all so is
+    // much faster for large files. Modification times are
+    // less reliable than hashes though, they change without
+    // the content changing (e.g touch) and can be set back
+    // to hide a change. With mtime_fallback_hash an xxhash
+    // is also stored and checked when the time differs.
+    #[serde(rename = "mtime")]
+    MtimeDiff,
+
     // Dont check if the files are different
     #[serde(rename = "disabled")]
     Disabled,
 }
 
+/// Prefixes of the stored hashes naming the algorithm they were made with
+const XXH3_PREFIX: &str = "xxh3:";
+const BLAKE3_PREFIX: &str = "blake3:";
+const MTIME_PREFIX: &str = "mtime:";
+
+/// Separates the modification time from its fallback hash
+const MTIME_HASH_SEPARATOR: char = ';';
+
 /// Checksum entry in stored metadata file
 #[derive(Deserialize, Serialize, Debug, Default)]
-struct ChecksumEntries {
-    entries: HashMap<PathBuf, String>,
+pub struct ChecksumEntries {
+    pub entries: HashMap<PathBuf, String>,
 }
 
 impl Default for FileCheckDiffStrategy {
@@ -51,39 +76,193 @@ impl Default for FileCheckDiffStrategy {
 
 impl FileCheckDiffStrategy {
     /// Returns the file path to the checksum storage
-    /// file in the metadata directory
-    fn get_checksum_file_path() -> anyhow::Result<PathBuf> {
+    /// file in the provided metadata directory
+    fn get_checksum_file_path(metadata_dir: &PathBuf) -> anyhow::Result<PathBuf> {
         // Get config to get file path.
         let apply_conf = &ROOT_CONFIG.get_config().apply;
 
-        Ok(apply_conf
-            .apply_metadata_dir
+        Ok(metadata_dir
             .join(&apply_conf.checkdiff_file_name)
             .clean_path()?)
     }
 
-    fn read_checksum_entries() -> anyhow::Result<ChecksumEntries> {
-        // Get file path..
-        let path = FileCheckDiffStrategy::get_checksum_file_path()?;
+    /// Gets the strategy from its name in the
+    /// configuration, e.g "xxhash"
+    pub fn from_name(name: &str) -> anyhow::Result<Self> {
+        match name {
+            "xxhash" => Ok(FileCheckDiffStrategy::XXHashDiff),
+            "blake3" => Ok(FileCheckDiffStrategy::Blake3Diff),
+            "mtime" => Ok(FileCheckDiffStrategy::MtimeDiff),
+            "disabled" => Ok(FileCheckDiffStrategy::Disabled),
+            _ => bail!("Unknown checkdiff strategy {}", name),
+        }
+    }
 
-        if !path.exists() {
-            return Ok(ChecksumEntries::default());
+    /// Hashes the file at the path with this strategy,
+    /// None if the strategy does not hash.
+    pub fn hash_file(self: &Self, path: &PathBuf) -> anyhow::Result<Option<String>> {
+        match self {
+            FileCheckDiffStrategy::Disabled => Ok(None),
+            FileCheckDiffStrategy::XXHashDiff => Ok(Some(xxhash_hash_file(path)?)),
+            FileCheckDiffStrategy::Blake3Diff => Ok(Some(blake3_hash_file(path)?)),
+            FileCheckDiffStrategy::MtimeDiff => Ok(Some(mtime_hash_file(path)?)),
         }
+    }
 
-        // Read in from file path
-        let file_content = fs::read_to_string(&path)
-            .with_context(|| format!("While trying to read checksum storage file {:?}", path))?;
+    /// Hashes content in memory the same way this strategy
+    /// hashes files, None if the strategy does not hash.
+    pub fn hash_bytes(self: &Self, bytes: &[u8]) -> Option<String> {
+        match self {
+            // Content in memory has no modification time
+            FileCheckDiffStrategy::Disabled | FileCheckDiffStrategy::MtimeDiff => None,
+            FileCheckDiffStrategy::XXHashDiff => Some(format!("{}{}", XXH3_PREFIX, xxh3_64(bytes))),
+            FileCheckDiffStrategy::Blake3Diff => {
+                Some(format!("{}{}", BLAKE3_PREFIX, blake3::hash(bytes).to_hex()))
+            }
+        }
+    }
 
-        ron::from_str(&file_content).with_context(|| {
-            format!(
-                "While trying to parse checksum storage file {:?}, Has it been tampered with?",
-                path
-            )
-        })
+    /// Strategy the stored hash was made with going by its prefix,
+    /// hashes stored before they were prefixed are all xxhash.
+    fn of_stored_hash(stored_hash: &str) -> Self {
+        if stored_hash.starts_with(BLAKE3_PREFIX) {
+            FileCheckDiffStrategy::Blake3Diff
+        } else if stored_hash.starts_with(MTIME_PREFIX) {
+            FileCheckDiffStrategy::MtimeDiff
+        } else {
+            FileCheckDiffStrategy::XXHashDiff
+        }
+    }
+
+    /// The stored hash with its prefix, adding
+    /// the xxhash one to hashes stored without one.
+    fn prefixed_stored_hash(stored_hash: &str) -> Cow<'_, str> {
+        if stored_hash.starts_with(XXH3_PREFIX)
+            || stored_hash.starts_with(BLAKE3_PREFIX)
+            || stored_hash.starts_with(MTIME_PREFIX)
+        {
+            Cow::Borrowed(stored_hash)
+        } else {
+            Cow::Owned(format!("{}{}", XXH3_PREFIX, stored_hash))
+        }
     }
 
-    fn write_checksum_entries(checksum_entries: &ChecksumEntries) -> anyhow::Result<()> {
-        let path = FileCheckDiffStrategy::get_checksum_file_path()?;
+    /// Hashes the file at the path the same way the stored hash was made,
+    /// so stored hashes stay comparable after the strategy changes.
+    pub fn hash_file_like_stored(stored_hash: &str, path: &PathBuf) -> anyhow::Result<String> {
+        match Self::of_stored_hash(stored_hash) {
+            FileCheckDiffStrategy::Blake3Diff => blake3_hash_file(path),
+            FileCheckDiffStrategy::MtimeDiff => {
+                mtime_stamp_file(path, stored_hash.contains(MTIME_HASH_SEPARATOR))
+            }
+            _ => xxhash_hash_file(path),
+        }
+    }
+
+    /// Whether the file at the path still matches the stored hash, a stored
+    /// modification time which differs falls back to its hash if it has one.
+    pub fn file_matches_stored(stored_hash: &str, path: &PathBuf) -> anyhow::Result<bool> {
+        if let Some(stored_stamp) = stored_hash.strip_prefix(MTIME_PREFIX) {
+            let (stored_mtime, fallback_hash) = match stored_stamp.split_once(MTIME_HASH_SEPARATOR)
+            {
+                Some((stored_mtime, fallback_hash)) => (stored_mtime, Some(fallback_hash)),
+                None => (stored_stamp, None),
+            };
+
+            if file_mtime(path)? == stored_mtime {
+                return Ok(true);
+            }
+
+            return match fallback_hash {
+                Some(fallback_hash) => Self::file_matches_stored(fallback_hash, path),
+                None => Ok(false),
+            };
+        }
+
+        Ok(Self::hash_file_like_stored(stored_hash, path)?
+            == Self::prefixed_stored_hash(stored_hash))
+    }
+
+    /// Whether the content in memory matches the stored hash, modification
+    /// times can only be compared through their fallback hash.
+    pub fn bytes_match_stored(stored_hash: &str, bytes: &[u8]) -> bool {
+        if let Some(stored_stamp) = stored_hash.strip_prefix(MTIME_PREFIX) {
+            return stored_stamp
+                .split_once(MTIME_HASH_SEPARATOR)
+                .is_some_and(|(_, fallback_hash)| Self::bytes_match_stored(fallback_hash, bytes));
+        }
+
+        Self::of_stored_hash(stored_hash)
+            .hash_bytes(bytes)
+            .is_some_and(|hash| hash == Self::prefixed_stored_hash(stored_hash))
+    }
+
+    /// Compares the current content of the destinations against the
+    /// checksums stored for them, returning the ones which don't match.
+    pub fn verify_checksums(
+        self: &Self,
+        destinations: &[PathBuf],
+        metadata_dirs: &[PathBuf],
+    ) -> anyhow::Result<Vec<PathBuf>> {
+        let checksum_entries = Self::read_checksum_entries(metadata_dirs)?;
+
+        let mut mismatched = Vec::new();
+        for destination in destinations {
+            let is_same = match (checksum_entries.entries.get(destination), self) {
+                (_, FileCheckDiffStrategy::Disabled) | (None, _) => false,
+                (Some(stored_hash), _) => Self::file_matches_stored(stored_hash, destination)
+                    .with_context(|| {
+                        format!(
+                            "While trying to verify the checksum of file {:?}",
+                            destination
+                        )
+                    })?,
+            };
+
+            if !is_same {
+                mismatched.push(destination.clone());
+            }
+        }
+
+        Ok(mismatched)
+    }
+
+    /// Reads the checksum entries stored in all of the
+    /// provided metadata directories, merged together.
+    pub fn read_checksum_entries(metadata_dirs: &[PathBuf]) -> anyhow::Result<ChecksumEntries> {
+        let mut checksum_entries = ChecksumEntries::default();
+
+        for metadata_dir in metadata_dirs {
+            // Get file path..
+            let path = FileCheckDiffStrategy::get_checksum_file_path(metadata_dir)?;
+
+            if !path.exists() {
+                continue;
+            }
+
+            // Read in from file path
+            let file_content = fs::read_to_string(&path).with_context(|| {
+                format!("While trying to read checksum storage file {:?}", path)
+            })?;
+
+            let dir_entries: ChecksumEntries = ron::from_str(&file_content).with_context(|| {
+                format!(
+                    "While trying to parse checksum storage file {:?}, Has it been tampered with?",
+                    path
+                )
+            })?;
+
+            checksum_entries.entries.extend(dir_entries.entries);
+        }
+
+        Ok(checksum_entries)
+    }
+
+    pub fn write_checksum_entries(
+        metadata_dir: &PathBuf,
+        checksum_entries: &ChecksumEntries,
+    ) -> anyhow::Result<()> {
+        let path = FileCheckDiffStrategy::get_checksum_file_path(metadata_dir)?;
 
         // Make parent directories if it doesn't exist already.
         if let Some(create_result) = path.parent().map(|path| fs::create_dir_all(path)) {
@@ -108,11 +287,13 @@ type HashFile = fn(file_path: &PathBuf) -> anyhow::Result<String>;
 
 fn xxhash_hash_file(path: &PathBuf) -> anyhow::Result<String> {
     let file = File::open(path).with_context(|| format!("While trying to hash file {:?}", path))?;
-    let mut reader = BufReader::new(file);
+    let copy_buffer_size = ROOT_CONFIG.get_config().apply.copy_buffer_size;
+
+    let mut reader = BufReader::with_capacity(copy_buffer_size, file);
 
-    // Buffer 64kb reads in from file at a time for hashing
+    // Buffer copy_buffer_size reads in from file at a time for hashing
     let mut hasher = Xxh3::new();
-    let mut buffer = [0u8; 65536];
+    let mut buffer = vec![0u8; copy_buffer_size];
 
     loop {
         // Update hash.
@@ -123,7 +304,62 @@ fn xxhash_hash_file(path: &PathBuf) -> anyhow::Result<String> {
         hasher.update(&buffer[..bytes_read]);
     }
 
-    Ok(format!("{}", hasher.digest()))
+    Ok(format!("{}{}", XXH3_PREFIX, hasher.digest()))
+}
+
+/// Blake3 version of hashing a file in from file path
+fn blake3_hash_file(path: &PathBuf) -> anyhow::Result<String> {
+    let file = File::open(path).with_context(|| format!("While trying to hash file {:?}", path))?;
+    let copy_buffer_size = ROOT_CONFIG.get_config().apply.copy_buffer_size;
+
+    let mut reader = BufReader::with_capacity(copy_buffer_size, file);
+
+    // Buffer copy_buffer_size reads in from file at a time for hashing
+    let mut hasher = blake3::Hasher::new();
+    let mut buffer = vec![0u8; copy_buffer_size];
+
+    loop {
+        let bytes_read = reader.read(&mut buffer)?;
+        if bytes_read == 0 {
+            break;
+        }
+        hasher.update(&buffer[..bytes_read]);
+    }
+
+    Ok(format!("{}{}", BLAKE3_PREFIX, hasher.finalize().to_hex()))
+}
+
+/// Modification time of the file as unix seconds and nanoseconds
+fn file_mtime(path: &PathBuf) -> anyhow::Result<String> {
+    let modified = fs::metadata(path)
+        .and_then(|metadata| metadata.modified())
+        .with_context(|| format!("While trying to get modification time of file {:?}", path))?
+        .duration_since(UNIX_EPOCH)?;
+
+    Ok(format!(
+        "{}.{:09}",
+        modified.as_secs(),
+        modified.subsec_nanos()
+    ))
+}
+
+/// Modification time version of "hashing" a file, with
+/// the xxhash of the file after it if with_hash is set
+fn mtime_stamp_file(path: &PathBuf, with_hash: bool) -> anyhow::Result<String> {
+    let mut stamp = format!("{}{}", MTIME_PREFIX, file_mtime(path)?);
+
+    if with_hash {
+        stamp.push(MTIME_HASH_SEPARATOR);
+        stamp.push_str(&xxhash_hash_file(path)?);
+    }
+
+    Ok(stamp)
+}
+
+/// Stamps the file with its modification time, with
+/// the xxhash too if mtime_fallback_hash is enabled
+fn mtime_hash_file(path: &PathBuf) -> anyhow::Result<String> {
+    mtime_stamp_file(path, ROOT_CONFIG.get_config().apply.mtime_fallback_hash)
 }
 
 /// Checks if the file is different
@@ -133,7 +369,7 @@ fn xxhash_hash_file(path: &PathBuf) -> anyhow::Result<String> {
 fn hash_check_diff(
     checksum_entries: &ChecksumEntries,
     file: &TrackedFile,
-    hash_fn: HashFile,
+    hook_strategy: &HookStrategy,
 ) -> anyhow::Result<()> {
     // New file, not yet in checkdiff, prompt user if not set to skip.
     if !checksum_entries.entries.contains_key(&file.destination) {
@@ -142,6 +378,14 @@ fn hash_check_diff(
             return Ok(());
         }
 
+        if ROOT_CONFIG.get_config().apply.overwrite_changed {
+            warn!(
+                "No existing hash checksum was found for {:?} referenced in configuration file {:?}, overwriting it",
+                file.destination, file.src
+            );
+            return Ok(());
+        }
+
         // Prompt for this case.
         let to_overwrite = Confirm::new(
             format!(
@@ -163,11 +407,23 @@ fn hash_check_diff(
     // Expected hash
     let expected_hash = checksum_entries.entries.get(&file.destination).unwrap();
 
-    // Hash file
-    let hash_result = hash_fn(&file.destination)?;
-
     // Same hash, no diff
-    if hash_result == *expected_hash {
+    if FileCheckDiffStrategy::file_matches_stored(expected_hash, &file.destination)? {
+        return Ok(());
+    }
+
+    // Hash file the same way as the expected hash
+    let hash_result =
+        FileCheckDiffStrategy::hash_file_like_stored(expected_hash, &file.destination)?;
+
+    // Notify about the mismatch before prompting
+    hook_strategy.execute_checkdiff_mismatch_hooks(file, expected_hash, &hash_result)?;
+
+    if ROOT_CONFIG.get_config().apply.overwrite_changed {
+        warn!(
+            "Checksum differs for file {:?} referenced by configuration file {:?} (it was changed between last apply), overwriting it",
+            file.destination, file.src
+        );
         return Ok(());
     }
 
@@ -213,12 +469,18 @@ fn hash_files_are_same(files: &TrackedFile, hash_fn: HashFile) -> bool {
 fn run_hash_strategy_before_copy(
     files: &mut TrackedFileList,
     hash_fn: HashFile,
+    hook_strategy: &HookStrategy,
 ) -> anyhow::Result<()> {
-    // Use checksum storage file.
-    let checksum_entries = FileCheckDiffStrategy::read_checksum_entries()?;
+    // Use checksum storage files.
+    let checksum_entries = FileCheckDiffStrategy::read_checksum_entries(&metadata_dirs(files))?;
 
     // No entries? Confirm with
     if checksum_entries.entries.len() < 1 {
+        if ROOT_CONFIG.get_config().apply.overwrite_changed {
+            warn!("No existing hash checksum storage was found, overwriting all to-apply files");
+            return Ok(());
+        }
+
         let to_overwrite = Confirm::new(
             format!(
                 "No existing hash checksum storage was found, Do you want to proceed? This will overwrite all to-apply files regardless of changes.",
@@ -237,7 +499,7 @@ fn run_hash_strategy_before_copy(
 
     // Check diff of every file.
     for file in &files.0 {
-        hash_check_diff(&checksum_entries, file, hash_fn)?;
+        hash_check_diff(&checksum_entries, file, hook_strategy)?;
     }
 
     // Check for checkdiff skip things
@@ -268,42 +530,159 @@ fn run_hash_strategy_before_copy(
 /// file using hash_fn to produce a hash
 /// for future use for diff checking
 fn run_hash_strategy_after_copy(files: &TrackedFileList, hash_fn: HashFile) -> anyhow::Result<()> {
-    // Use checksum storage file that already exists
-    // to keep entries we may have lost
-    let mut checksum_entries = FileCheckDiffStrategy::read_checksum_entries()?;
+    // Every metadata directory has its own storage file
+    for metadata_dir in metadata_dirs(files) {
+        // Use checksum storage file that already exists
+        // to keep entries we may have lost
+        let mut checksum_entries =
+            FileCheckDiffStrategy::read_checksum_entries(std::slice::from_ref(&metadata_dir))?;
+
+        for file in files.iter() {
+            if *file_metadata_dir(file) != metadata_dir {
+                continue;
+            }
 
-    for file in &files.0 {
-        // Insert with the new hash..
-        checksum_entries.entries.insert(
-            PathBuf::from(&file.destination),
-            hash_fn(&file.destination)?,
-        );
-    }
+            // Insert with the new hash..
+            checksum_entries.entries.insert(
+                PathBuf::from(&file.destination),
+                hash_fn(&file.destination)?,
+            );
+        }
 
-    // Write to the file
-    FileCheckDiffStrategy::write_checksum_entries(&checksum_entries)?;
+        // Write to the file
+        FileCheckDiffStrategy::write_checksum_entries(&metadata_dir, &checksum_entries)?;
+    }
 
     Ok(())
 }
 
-impl ApplyStrategy for FileCheckDiffStrategy {
+/// Checkdiff strategy of an apply along with the hooks to
+/// run for destinations which were changed outside of typewriter
+pub struct CheckDiffApplying<'a> {
+    strategy: &'a FileCheckDiffStrategy,
+    hook_strategy: &'a HookStrategy,
+}
+
+impl<'a> CheckDiffApplying<'a> {
+    pub fn new(strategy: &'a FileCheckDiffStrategy, hook_strategy: &'a HookStrategy) -> Self {
+        Self {
+            strategy,
+            hook_strategy,
+        }
+    }
+}
+
+impl ApplyStrategy for CheckDiffApplying<'_> {
+    fn name(self: &Self) -> &'static str {
+        "checkdiff"
+    }
+
+    fn variant(self: &Self) -> String {
+        format!("{:?}", self.strategy)
+    }
+
     fn run_before_apply(self: &Self, files: &mut TrackedFileList) -> anyhow::Result<()> {
         // Specific method for checking file diff.
-        match self {
+        match self.strategy {
             FileCheckDiffStrategy::Disabled => Ok(()),
             FileCheckDiffStrategy::XXHashDiff => {
-                run_hash_strategy_before_copy(files, xxhash_hash_file)
+                run_hash_strategy_before_copy(files, xxhash_hash_file, self.hook_strategy)
+            }
+            FileCheckDiffStrategy::Blake3Diff => {
+                run_hash_strategy_before_copy(files, blake3_hash_file, self.hook_strategy)
+            }
+            // Modification times of the source and destination say
+            // nothing about their content, so compare them with xxhash
+            FileCheckDiffStrategy::MtimeDiff => {
+                run_hash_strategy_before_copy(files, xxhash_hash_file, self.hook_strategy)
             }
         }
     }
 
     fn run_after_apply(self: &Self, files: &mut TrackedFileList) -> anyhow::Result<()> {
         // Method for writing checksum back after copying
-        match self {
+        match self.strategy {
             FileCheckDiffStrategy::Disabled => Ok(()),
             FileCheckDiffStrategy::XXHashDiff => {
                 run_hash_strategy_after_copy(files, xxhash_hash_file)
             }
+            FileCheckDiffStrategy::Blake3Diff => {
+                run_hash_strategy_after_copy(files, blake3_hash_file)
+            }
+            FileCheckDiffStrategy::MtimeDiff => {
+                run_hash_strategy_after_copy(files, mtime_hash_file)
+            }
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+
+    /// Content of the file hashed by the tests
+    const CONTENT: &[u8] = b"typewriter checkdiff test\n";
+
+    /// Expected digests of the content
+    const XXH3_DIGEST: &str = "xxh3:12346042067795391404";
+    const BLAKE3_DIGEST: &str =
+        "blake3:298061e248a8f2115b3e5a1bfb37fde7068d23823fbdd526b9df771b60b8c157";
+
+    /// Writes the content to a file in a temporary directory, with the
+    /// default config set since hashing reads the copy buffer size
+    fn hashed_file() -> (tempfile::TempDir, PathBuf) {
+        ROOT_CONFIG.set_config(Config::default());
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("hashed.file");
+        fs::write(&path, CONTENT).unwrap();
+        (dir, path)
+    }
+
+    #[test]
+    fn xxhash_digest_is_stable() {
+        let (_dir, path) = hashed_file();
+
+        assert_eq!(xxhash_hash_file(&path).unwrap(), XXH3_DIGEST);
+        assert_eq!(xxhash_hash_file(&path).unwrap(), XXH3_DIGEST);
+        assert_eq!(
+            FileCheckDiffStrategy::XXHashDiff.hash_bytes(CONTENT),
+            Some(XXH3_DIGEST.to_string())
+        );
+    }
+
+    #[test]
+    fn blake3_digest_is_stable() {
+        let (_dir, path) = hashed_file();
+
+        assert_eq!(blake3_hash_file(&path).unwrap(), BLAKE3_DIGEST);
+        assert_eq!(blake3_hash_file(&path).unwrap(), BLAKE3_DIGEST);
+        assert_eq!(
+            FileCheckDiffStrategy::Blake3Diff.hash_bytes(CONTENT),
+            Some(BLAKE3_DIGEST.to_string())
+        );
+    }
+
+    #[test]
+    fn stored_hashes_are_compared_with_their_own_algorithm() {
+        let (_dir, path) = hashed_file();
+
+        assert_eq!(
+            FileCheckDiffStrategy::hash_file_like_stored(BLAKE3_DIGEST, &path).unwrap(),
+            BLAKE3_DIGEST
+        );
+        assert_eq!(
+            FileCheckDiffStrategy::hash_file_like_stored(XXH3_DIGEST, &path).unwrap(),
+            XXH3_DIGEST
+        );
+
+        // Hashes stored before prefixes were added are xxhash
+        let unprefixed = XXH3_DIGEST.strip_prefix(XXH3_PREFIX).unwrap();
+        assert!(FileCheckDiffStrategy::file_matches_stored(unprefixed, &path).unwrap());
+        assert!(FileCheckDiffStrategy::bytes_match_stored(
+            BLAKE3_DIGEST,
+            CONTENT
+        ));
+    }
+}
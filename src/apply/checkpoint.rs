@@ -0,0 +1,99 @@
+//! Checkpoint of the files completed by an apply
+//! that failed partway through, so that a later
+//! apply can resume from where it stopped
+
+use std::{
+    collections::{HashMap, HashSet},
+    fs,
+    path::PathBuf,
+};
+
+use anyhow::Context;
+use log::info;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    apply::{
+        group_by_metadata_dir,
+        store::{read_ron, write_ron},
+    },
+    cleanpath::CleanPath,
+};
+
+/// Name of the checkpoint file in the metadata directory
+const CHECKPOINT_FILE_NAME: &str = ".apply_checkpoint";
+
+/// Checkpoint stored in the metadata directory
+#[derive(Deserialize, Serialize, Debug, Default)]
+struct ApplyCheckpoint {
+    // Destinations of the files which were fully applied
+    completed: HashSet<PathBuf>,
+}
+
+/// Returns the file path to the checkpoint
+/// file in the metadata directory
+fn get_checkpoint_file_path(metadata_dir: &PathBuf) -> anyhow::Result<PathBuf> {
+    metadata_dir.join(CHECKPOINT_FILE_NAME).clean_path()
+}
+
+/// Reads the checkpoint from a single metadata directory
+fn read_dir_checkpoint(metadata_dir: &PathBuf) -> anyhow::Result<HashSet<PathBuf>> {
+    let path = get_checkpoint_file_path(metadata_dir)?;
+    let checkpoint: Option<ApplyCheckpoint> = read_ron(&path, "apply checkpoint")?;
+
+    Ok(checkpoint.unwrap_or_default().completed)
+}
+
+/// Reads the destinations of completed files from the checkpoints
+/// of all of the metadata directories, empty if there are none.
+pub fn read_checkpoint(metadata_dirs: &[PathBuf]) -> anyhow::Result<HashSet<PathBuf>> {
+    let mut completed = HashSet::new();
+    for metadata_dir in metadata_dirs {
+        completed.extend(read_dir_checkpoint(metadata_dir)?);
+    }
+
+    Ok(completed)
+}
+
+/// Adds the destinations of completed files to the checkpoint in
+/// the metadata directory of their file, keeping the ones from
+/// before (for multiple resumes)
+pub fn save_checkpoint(
+    completed: &[PathBuf],
+    destination_dirs: &HashMap<PathBuf, PathBuf>,
+) -> anyhow::Result<()> {
+    for (metadata_dir, destinations) in group_by_metadata_dir(completed, destination_dirs) {
+        let path = get_checkpoint_file_path(&metadata_dir)?;
+
+        let mut checkpoint = ApplyCheckpoint {
+            completed: read_dir_checkpoint(&metadata_dir)?,
+        };
+        checkpoint.completed.extend(destinations);
+
+        write_ron(&path, &checkpoint, "apply checkpoint")?;
+
+        info!(
+            "Saved {} completed file(s) to apply checkpoint {:?}",
+            checkpoint.completed.len(),
+            path
+        );
+    }
+
+    Ok(())
+}
+
+/// Removes the checkpoint files of all of
+/// the metadata directories if they exist
+pub fn remove_checkpoint(metadata_dirs: &[PathBuf]) -> anyhow::Result<()> {
+    for metadata_dir in metadata_dirs {
+        let path = get_checkpoint_file_path(metadata_dir)?;
+
+        if path.exists() {
+            fs::remove_file(&path).with_context(|| {
+                format!("While trying to remove apply checkpoint file {:?}", path)
+            })?;
+        }
+    }
+
+    Ok(())
+}
@@ -0,0 +1,123 @@
+//! Comparison of the flattened data of two typewriter
+//! configurations, for auditing e.g production vs staging
+
+use std::{collections::BTreeMap, path::PathBuf};
+
+use ansi_term::Color::{Green, Red, White, Yellow};
+use serde_json::Value;
+
+use crate::{
+    apply::hooks::HookList, file::TrackedFileList, log::paint, parse_config::parse_config_data,
+    vars::VariableList,
+};
+
+/// Serializes every item into a comparable value
+fn to_values<T: serde::Serialize>(items: &[T]) -> anyhow::Result<Vec<Value>> {
+    Ok(items
+        .iter()
+        .map(serde_json::to_value)
+        .collect::<Result<_, _>>()?)
+}
+
+/// Maps the variables by name to their comparable value
+fn variable_values(variables: &VariableList) -> anyhow::Result<BTreeMap<String, Value>> {
+    variables
+        .iter()
+        .map(|variable| Ok((variable.name.clone(), serde_json::to_value(variable)?)))
+        .collect()
+}
+
+/// Prints a heading followed by every line under it, if there are any
+fn print_section(heading: &str, lines: &[String]) {
+    if lines.is_empty() {
+        return;
+    }
+
+    println!("{}", paint(White.bold(), heading));
+    for line in lines {
+        println!("  {}", line);
+    }
+}
+
+/// Compares the data of the already loaded configuration with the
+/// other configuration file, printing every difference between them.
+///
+/// Returns whether or not the configurations are equivalent.
+pub fn diff_configs(
+    file: &str,
+    files: &TrackedFileList,
+    variables: &VariableList,
+    hooks: &HookList,
+    other_file: &str,
+    section: String,
+) -> anyhow::Result<bool> {
+    let (other_files, other_variables, other_hooks) = parse_config_data(other_file, section)?;
+
+    // Files are matched up by their destination
+    let destinations: Vec<&PathBuf> = files.iter().map(|file| &file.destination).collect();
+    let other_destinations: Vec<&PathBuf> =
+        other_files.iter().map(|file| &file.destination).collect();
+
+    let only_primary: Vec<String> = destinations
+        .iter()
+        .filter(|destination| !other_destinations.contains(destination))
+        .map(|destination| paint(Red.normal(), format!("{:?}", destination)).to_string())
+        .collect();
+    let only_other: Vec<String> = other_destinations
+        .iter()
+        .filter(|destination| !destinations.contains(destination))
+        .map(|destination| paint(Green.normal(), format!("{:?}", destination)).to_string())
+        .collect();
+    let in_both: Vec<String> = destinations
+        .iter()
+        .filter(|destination| other_destinations.contains(destination))
+        .map(|destination| format!("{:?}", destination))
+        .collect();
+
+    // Variables are matched up by name
+    let variable_values_primary = variable_values(variables)?;
+    let variable_values_other = variable_values(&other_variables)?;
+
+    let mut changed_variables = Vec::new();
+    for (name, value) in &variable_values_primary {
+        match variable_values_other.get(name) {
+            Some(other_value) if other_value == value => {}
+            Some(_) => {
+                changed_variables.push(paint(Yellow.normal(), format!("~ {}", name)).to_string())
+            }
+            None => changed_variables.push(paint(Red.normal(), format!("- {}", name)).to_string()),
+        }
+    }
+    for name in variable_values_other.keys() {
+        if !variable_values_primary.contains_key(name) {
+            changed_variables.push(paint(Green.normal(), format!("+ {}", name)).to_string());
+        }
+    }
+
+    // Hooks have no name so any hook without an identical one is changed
+    let hook_values = to_values(hooks)?;
+    let other_hook_values = to_values(&other_hooks)?;
+
+    let mut changed_hooks = Vec::new();
+    for (hook, value) in hooks.iter().zip(&hook_values) {
+        if !other_hook_values.contains(value) {
+            changed_hooks.push(paint(Red.normal(), format!("- {}", hook.command)).to_string());
+        }
+    }
+    for (hook, value) in other_hooks.iter().zip(&other_hook_values) {
+        if !hook_values.contains(value) {
+            changed_hooks.push(paint(Green.normal(), format!("+ {}", hook.command)).to_string());
+        }
+    }
+
+    print_section(&format!("Files only in {}", file), &only_primary);
+    print_section(&format!("Files only in {}", other_file), &only_other);
+    print_section("Files in both", &in_both);
+    print_section("Variables changed", &changed_variables);
+    print_section("Hooks changed", &changed_hooks);
+
+    Ok(only_primary.is_empty()
+        && only_other.is_empty()
+        && changed_variables.is_empty()
+        && changed_hooks.is_empty())
+}
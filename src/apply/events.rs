@@ -0,0 +1,52 @@
+//! Publishing events of an apply as they happen to
+//! the event socket, for external tools to react to
+
+use std::path::PathBuf;
+
+use log::debug;
+use serde::Serialize;
+
+use crate::config::ROOT_CONFIG;
+
+/// Event of the apply, serialized as
+/// {"event": "<name>", ...fields}
+#[derive(Serialize, Debug)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum ApplyEvent {
+    // A file was applied to its destination
+    FileApplied { destination: PathBuf },
+
+    // The apply is done, with how many files were applied
+    ApplyComplete { count: usize },
+}
+
+/// Publishes the event to the event socket if one is configured,
+/// nothing listening on the socket is not an error since
+/// the events are only informational.
+pub fn publish_event(event: &ApplyEvent) {
+    let Some(event_socket) = &ROOT_CONFIG.get_config().apply.event_socket else {
+        return;
+    };
+
+    if let Err(e) = send_event(event_socket, event) {
+        debug!(
+            "Failed to publish event {:?} to event socket {:?}: {:?}",
+            event, event_socket, e
+        );
+    }
+}
+
+/// Sends the event as JSON in a datagram to the socket
+#[cfg(unix)]
+fn send_event(event_socket: &PathBuf, event: &ApplyEvent) -> anyhow::Result<()> {
+    use std::os::unix::net::UnixDatagram;
+
+    let socket = UnixDatagram::unbound()?;
+    socket.send_to(serde_json::to_string(event)?.as_bytes(), event_socket)?;
+    Ok(())
+}
+
+#[cfg(not(unix))]
+fn send_event(_event_socket: &PathBuf, _event: &ApplyEvent) -> anyhow::Result<()> {
+    anyhow::bail!("Event sockets are only supported on unix")
+}
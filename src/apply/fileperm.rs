@@ -11,18 +11,19 @@ use std::{
 
 use anyhow::{Context, bail};
 use inquire::Confirm;
-use log::{error, info};
-use serde::Deserialize;
+use log::{error, info, warn};
+use serde::{Deserialize, Serialize};
 
 use crate::{
-    apply::strategy::ApplyStrategy,
+    apply::{preserve::copy_source_permissions, strategy::ApplyStrategy},
+    cleanpath::CleanPath,
     config::ROOT_CONFIG,
     file::{TrackedFile, TrackedFileList},
 };
 
 /// Strategy for checking file permissions and
 /// optionally creating missing destination files
-#[derive(Deserialize, Debug)]
+#[derive(Deserialize, Serialize, Debug)]
 pub enum FilePermissionStrategy {
     // Only check file permissions, do not create missing files
     #[serde(rename = "check_only")]
@@ -32,6 +33,11 @@ pub enum FilePermissionStrategy {
     #[serde(rename = "create_if_missing")]
     CreateIfMissing,
 
+    // Check file permissions, then set the permission mode of
+    // destinations after applying, default_mode for files without one
+    #[serde(rename = "set_mode")]
+    SetMode { default_mode: u32 },
+
     // Disable file permission checking entirely
     #[serde(rename = "disabled")]
     Disabled,
@@ -45,7 +51,9 @@ impl Default for FilePermissionStrategy {
 
 // Track created files for potential cleanup on failure, this
 // is thread_local because static declarations need to be Sync
-// but we are only using it in a single thread context anyway.
+// but we are only using it in a single thread context anyway,
+// files are only created in run_before_apply which is never
+// ran in parallel (unlike the per-file stages).
 thread_local! {
     static CREATED_FILES: RefCell<Option<HashSet<PathBuf>>> = RefCell::new(None);
 }
@@ -132,6 +140,9 @@ impl FilePermissionStrategy {
             )
         })?;
 
+        // Otherwise the mode preserved would be the default one it was created with
+        copy_source_permissions(file)?;
+
         // Track created file for cleanup on failure
         CREATED_FILES.with(|created| {
             if let Some(ref mut set) = *created.borrow_mut() {
@@ -147,6 +158,81 @@ impl FilePermissionStrategy {
         Ok(())
     }
 
+    /// Checks a symlinked destination of the file still points at its
+    /// link_target, files are written through their destination so a
+    /// hijacked link means the write would end up in whatever file it
+    /// was pointed at instead. Files without a link_target aren't checked.
+    fn check_destination_link(file: &TrackedFile) -> anyhow::Result<()> {
+        let Some(link_target) = &file.link_target else {
+            return Ok(());
+        };
+
+        if !file.destination.is_symlink() {
+            return Ok(());
+        }
+
+        let target = fs::read_link(&file.destination).with_context(|| {
+            format!(
+                "While reading the target of symlinked destination file {:?}",
+                file.destination
+            )
+        })?;
+
+        // Relative targets are relative to the directory of the link
+        let resolved_target = match file.destination.parent() {
+            Some(destination_dir) => destination_dir.join(&target).clean_path()?,
+            None => target.clean_path()?,
+        };
+
+        if &resolved_target == link_target {
+            return Ok(());
+        }
+
+        if ROOT_CONFIG.get_config().apply.auto_skip_unable_apply {
+            bail!(
+                "Destination file {:?} referenced in configuration file {:?} is a symlink to {:?} instead of {:?}, refusing to write through it",
+                file.destination,
+                file.src,
+                target,
+                link_target
+            );
+        }
+
+        warn!(
+            "Destination file {:?} referenced in configuration file {:?} is a symlink to {:?} instead of {:?}, the apply will write to its target",
+            file.destination, file.src, target, link_target
+        );
+
+        Ok(())
+    }
+
+    /// Sets the permission mode of the destination of the file,
+    /// to its own mode if it has one or the default mode otherwise.
+    #[cfg(unix)]
+    fn set_file_mode(file: &TrackedFile, default_mode: u32) -> anyhow::Result<()> {
+        use std::os::unix::fs::PermissionsExt;
+
+        let mode = file.mode.unwrap_or(default_mode);
+        fs::set_permissions(&file.destination, fs::Permissions::from_mode(mode)).with_context(
+            || {
+                format!(
+                    "While setting mode {:o} on destination file {:?} referenced in configuration file {:?}",
+                    mode, file.destination, file.src
+                )
+            },
+        )
+    }
+
+    /// Permission modes are unix-only, so nothing is set elsewhere.
+    #[cfg(not(unix))]
+    fn set_file_mode(file: &TrackedFile, _default_mode: u32) -> anyhow::Result<()> {
+        warn!(
+            "Cannot set the mode of destination file {:?}, permission modes are only supported on unix",
+            file.destination
+        );
+        Ok(())
+    }
+
     /// Validates file permissions and optionally creates missing files.
     ///
     /// Checks that source file is readable and destination file is writable.
@@ -158,6 +244,11 @@ impl FilePermissionStrategy {
         src_options.read(true);
         Self::check_path_access(&file.file, &file.src, src_options, "read")?;
 
+        // Check a symlinked destination wasn't pointed elsewhere
+        if ROOT_CONFIG.get_config().apply.link_verification {
+            Self::check_destination_link(file)?;
+        }
+
         // Check destination file existence and create if needed
         let dest_exists = file.destination.exists();
         if !dest_exists && create_missing {
@@ -175,6 +266,14 @@ impl FilePermissionStrategy {
 }
 
 impl ApplyStrategy for FilePermissionStrategy {
+    fn name(&self) -> &'static str {
+        "file_permissions"
+    }
+
+    fn variant(&self) -> String {
+        format!("{:?}", self)
+    }
+
     fn run_before_apply(&self, files: &mut TrackedFileList) -> anyhow::Result<()> {
         // Initialize created files tracking
         CREATED_FILES.with(|created| {
@@ -183,7 +282,7 @@ impl ApplyStrategy for FilePermissionStrategy {
 
         match self {
             FilePermissionStrategy::Disabled => Ok(()),
-            FilePermissionStrategy::CheckOnly => {
+            FilePermissionStrategy::CheckOnly | FilePermissionStrategy::SetMode { .. } => {
                 for file in files.iter() {
                     self.check_file_perms(file, false)?;
                 }
@@ -223,11 +322,18 @@ impl ApplyStrategy for FilePermissionStrategy {
         Ok(())
     }
 
-    fn run_after_apply(&self, _files: &mut TrackedFileList) -> anyhow::Result<()> {
+    fn run_after_apply(&self, files: &mut TrackedFileList) -> anyhow::Result<()> {
         // Clear created files tracking after successful apply
         CREATED_FILES.with(|created| {
             *created.borrow_mut() = None;
         });
+
+        if let FilePermissionStrategy::SetMode { default_mode } = self {
+            for file in files.iter() {
+                Self::set_file_mode(file, *default_mode)?;
+            }
+        }
+
         Ok(())
     }
 }
@@ -0,0 +1,83 @@
+//! History of when each destination file was
+//! last successfully applied, for only applying
+//! files which haven't been applied since a time
+
+use std::{
+    collections::HashMap,
+    path::PathBuf,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    apply::{
+        group_by_metadata_dir,
+        store::{read_ron, write_ron},
+    },
+    cleanpath::CleanPath,
+};
+
+/// Name of the history file in the metadata directory
+const HISTORY_FILE_NAME: &str = ".apply_history";
+
+/// Apply history stored in the metadata directory
+#[derive(Deserialize, Serialize, Debug, Default)]
+struct ApplyHistory {
+    // Unix timestamp (seconds) of the last successful
+    // apply of each destination
+    last_applied: HashMap<PathBuf, i64>,
+}
+
+/// Returns the file path to the history
+/// file in the metadata directory
+fn get_history_file_path(metadata_dir: &PathBuf) -> anyhow::Result<PathBuf> {
+    metadata_dir.join(HISTORY_FILE_NAME).clean_path()
+}
+
+/// Reads the history from a single metadata directory
+fn read_dir_history(metadata_dir: &PathBuf) -> anyhow::Result<HashMap<PathBuf, i64>> {
+    let history: Option<ApplyHistory> =
+        read_ron(&get_history_file_path(metadata_dir)?, "apply history")?;
+
+    Ok(history.unwrap_or_default().last_applied)
+}
+
+/// Reads the unix timestamp of the last successful apply of each
+/// destination from all of the metadata directories, empty if
+/// nothing has been applied yet.
+pub fn read_history(metadata_dirs: &[PathBuf]) -> anyhow::Result<HashMap<PathBuf, i64>> {
+    let mut last_applied = HashMap::new();
+    for metadata_dir in metadata_dirs {
+        last_applied.extend(read_dir_history(metadata_dir)?);
+    }
+
+    Ok(last_applied)
+}
+
+/// Records the destinations as applied now in the history of the
+/// metadata directory of their file, keeping the ones of other
+/// destinations from before
+pub fn record_applied(
+    applied: &[PathBuf],
+    destination_dirs: &HashMap<PathBuf, PathBuf>,
+) -> anyhow::Result<()> {
+    let now = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs() as i64;
+
+    for (metadata_dir, destinations) in group_by_metadata_dir(applied, destination_dirs) {
+        let mut history = ApplyHistory {
+            last_applied: read_dir_history(&metadata_dir)?,
+        };
+        for destination in destinations {
+            history.last_applied.insert(destination, now);
+        }
+
+        write_ron(
+            &get_history_file_path(&metadata_dir)?,
+            &history,
+            "apply history",
+        )?;
+    }
+
+    Ok(())
+}
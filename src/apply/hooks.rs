@@ -2,7 +2,7 @@
 
 use anyhow::{Context, Result, bail};
 use log::{error, info, warn};
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 use std::path::{Path, PathBuf};
 
 use crate::{
@@ -11,6 +11,7 @@ use crate::{
     command::{CommandContext, execute_command},
     config::ROOT_CONFIG,
     file::{TrackedFile, TrackedFileList},
+    log::typewriter_warn,
 };
 
 /// Hook execution stages
@@ -18,10 +19,12 @@ use crate::{
 pub enum HookStage {
     PreApply,
     PostApply,
+    OnCheckdiffMismatch,
+    OnRollback,
 }
 
 /// Definition of a hook from configuration
-#[derive(Deserialize, Debug)]
+#[derive(Deserialize, Serialize, Debug)]
 #[serde(deny_unknown_fields)]
 pub struct HookDefinition {
     // The command to execute
@@ -34,13 +37,34 @@ pub struct HookDefinition {
     #[serde(default)]
     pub continue_on_error: bool,
 
+    // Should the stdout of this hook be parsed as KEY=VALUE lines
+    // and passed as environment variables to the subsequent hooks
+    // in the same stage?
+    #[serde(default)]
+    pub capture_env: bool,
+
+    // Should the stdout of this hook be given to the
+    // next hook in the same stage on its stdin?
+    #[serde(default)]
+    pub pipe_to_next: bool,
+
+    // Directory to run this hook in instead of the directory
+    // of its configuration file (relative to that directory)
+    #[serde(default)]
+    pub working_dir: Option<PathBuf>,
+
+    // Human-readable explanation of what this hook
+    // does, shown when confirming it is ran
+    #[serde(default)]
+    pub description: Option<String>,
+
     // Source file tracking (added during parsing)
     #[serde(skip)]
     pub src: PathBuf,
 }
 
 /// Failure strategy for hooks
-#[derive(Debug, Clone, Deserialize)]
+#[derive(Debug, Clone, Deserialize, Serialize)]
 pub enum FailureStrategy {
     // Stop entire apply on hook failure
     #[serde(rename = "abort")]
@@ -58,7 +82,7 @@ impl Default for FailureStrategy {
 }
 
 /// Wrapper list for hooks
-#[derive(Deserialize, Default, Debug)]
+#[derive(Deserialize, Serialize, Default, Debug)]
 pub struct HookList(pub Vec<HookDefinition>);
 
 impl std::ops::Deref for HookList {
@@ -81,7 +105,7 @@ impl FromIterator<HookDefinition> for HookList {
 }
 
 /// Hook configuration options
-#[derive(Deserialize, Debug, Clone)]
+#[derive(Deserialize, Serialize, Debug, Clone)]
 #[serde(deny_unknown_fields)]
 pub struct HooksConfig {
     // Whether or not hooks should be enabled in typewriter
@@ -91,6 +115,11 @@ pub struct HooksConfig {
     // Strategy to use on failure of hooks
     #[serde(default)]
     pub failure_strategy: FailureStrategy,
+
+    // Maximum size in bytes of the output of each hook
+    // to keep, the rest is discarded (still displayed)
+    #[serde(default)]
+    pub max_hook_output_size: Option<usize>,
 }
 
 impl Default for HooksConfig {
@@ -98,6 +127,7 @@ impl Default for HooksConfig {
         Self {
             hooks_enabled: default_true(),
             failure_strategy: FailureStrategy::default(),
+            max_hook_output_size: None,
         }
     }
 }
@@ -118,8 +148,10 @@ impl HookDefinition {
         match self.stage.as_str() {
             "pre_apply" => Ok(HookStage::PreApply),
             "post_apply" => Ok(HookStage::PostApply),
+            "on_checkdiff_mismatch" => Ok(HookStage::OnCheckdiffMismatch),
+            "on_rollback" => Ok(HookStage::OnRollback),
             _ => bail!(
-                "Invalid hook stage '{}' in {:?}. Must be 'pre_apply' or 'post_apply'",
+                "Invalid hook stage '{}' in {:?}. Must be 'pre_apply', 'post_apply', 'on_checkdiff_mismatch' or 'on_rollback'",
                 self.stage,
                 self.src
             ),
@@ -127,10 +159,43 @@ impl HookDefinition {
     }
 }
 
+/// Parses the output of a hook as newline-separated KEY=VALUE
+/// lines (like a .env file), ignoring empty lines and comments.
+fn parse_captured_env(output: &str, src: &Path) -> Vec<(String, String)> {
+    let mut env_vars = Vec::new();
+
+    for line in output.lines() {
+        let line = line.trim();
+
+        // Skip empty lines and comments
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        match line.split_once('=') {
+            Some((key, value)) if !key.trim().is_empty() => {
+                env_vars.push((key.trim().to_string(), value.trim().to_string()));
+            }
+            _ => warn!(
+                "Ignoring malformed captured environment line '{}' from hook in {:?}",
+                line, src
+            ),
+        }
+    }
+
+    env_vars
+}
+
 /// Strategy wrapper for hooks integration with ApplyStrategy trait
 pub struct HookStrategy {
     pre_apply_hooks: Vec<HookDefinition>,
     post_apply_hooks: Vec<HookDefinition>,
+
+    // Ran by the checkdiff strategy rather than this strategy
+    checkdiff_mismatch_hooks: Vec<HookDefinition>,
+
+    // Ran after every strategy has rolled back a failed apply
+    rollback_hooks: Vec<HookDefinition>,
 }
 
 impl HookStrategy {
@@ -138,48 +203,144 @@ impl HookStrategy {
         // Group hooks by stage, validating stages
         let mut pre_apply_hooks = Vec::new();
         let mut post_apply_hooks = Vec::new();
+        let mut checkdiff_mismatch_hooks = Vec::new();
+        let mut rollback_hooks = Vec::new();
 
         for hook in hooks.0 {
             match hook.parse_stage()? {
                 HookStage::PreApply => pre_apply_hooks.push(hook),
                 HookStage::PostApply => post_apply_hooks.push(hook),
+                HookStage::OnCheckdiffMismatch => checkdiff_mismatch_hooks.push(hook),
+                HookStage::OnRollback => rollback_hooks.push(hook),
             }
         }
 
         Ok(Self {
             pre_apply_hooks,
             post_apply_hooks,
+            checkdiff_mismatch_hooks,
+            rollback_hooks,
         })
     }
 
+    /// Commands of all of the global hooks in the
+    /// order they would be run in
+    pub fn global_hook_commands(&self) -> Vec<String> {
+        self.pre_apply_hooks
+            .iter()
+            .chain(self.post_apply_hooks.iter())
+            .map(|hook| hook.command.clone())
+            .collect()
+    }
+
+    /// Execute the hooks for a checkdiff mismatch of the file, with the
+    /// expected and actual hashes of its destination in the environment
+    pub fn execute_checkdiff_mismatch_hooks(
+        &self,
+        file: &TrackedFile,
+        expected_hash: &str,
+        actual_hash: &str,
+    ) -> Result<()> {
+        if !ROOT_CONFIG.get_config().hooks.hooks_enabled {
+            return Ok(());
+        }
+
+        let hash_env = [
+            (
+                "TYPEWRITER_EXPECTED_HASH".to_string(),
+                expected_hash.to_string(),
+            ),
+            (
+                "TYPEWRITER_ACTUAL_HASH".to_string(),
+                actual_hash.to_string(),
+            ),
+        ];
+
+        for hook in &self.checkdiff_mismatch_hooks {
+            if let Err(e) =
+                Self::execute_hook(hook, Some((&file.file, &file.destination)), &hash_env, None)
+            {
+                Self::handle_hook_error(&hook.command, &hook.src, e, hook.continue_on_error)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Execute the hooks for rolling back a failed apply, with
+    /// the error the apply failed with in the environment
+    fn execute_rollback_hooks(&self, reason: &str) -> Result<()> {
+        if !ROOT_CONFIG.get_config().hooks.hooks_enabled {
+            return Ok(());
+        }
+
+        let reason_env = [("TYPEWRITER_ROLLBACK_REASON".to_string(), reason.to_string())];
+
+        for hook in &self.rollback_hooks {
+            if let Err(e) = Self::execute_hook(hook, None, &reason_env, None) {
+                Self::handle_hook_error(&hook.command, &hook.src, e, hook.continue_on_error)?;
+            }
+        }
+
+        Ok(())
+    }
+
     /// Execute hooks for a specific stage
     fn execute_stage_hooks(&self, hooks: &[HookDefinition]) -> Result<()> {
         if !ROOT_CONFIG.get_config().hooks.hooks_enabled || hooks.is_empty() {
             return Ok(());
         }
 
+        // Environment captured from hooks with capture_env
+        // passed along to every subsequent hook in this stage
+        let mut captured_env: Vec<(String, String)> = Vec::new();
+
+        // Stdout of the previous hook if it had pipe_to_next
+        let mut piped_stdin: Option<String> = None;
+
         for hook in hooks {
-            if let Err(e) = self.execute_hook(hook, None) {
-                self.handle_hook_error(&hook.command, &hook.src, e, hook.continue_on_error)?;
+            match Self::execute_hook(hook, None, &captured_env, piped_stdin.take()) {
+                Ok(output) => {
+                    if hook.capture_env {
+                        captured_env.extend(parse_captured_env(&output, &hook.src));
+                    }
+
+                    if hook.pipe_to_next {
+                        piped_stdin = Some(output);
+                    }
+                }
+                Err(e) => {
+                    Self::handle_hook_error(&hook.command, &hook.src, e, hook.continue_on_error)?;
+                }
             }
         }
 
         Ok(())
     }
 
-    /// Execute a single hook
+    /// Execute a single hook, returning its stdout
     fn execute_hook(
-        &self,
         hook: &HookDefinition,
         file_context: Option<(&Path, &Path)>,
-    ) -> Result<()> {
+        extra_env: &[(String, String)],
+        stdin_data: Option<String>,
+    ) -> Result<String> {
         let mut context = CommandContext::default();
-        context.workdir = Some(hook.src.parent().with_context(
+        context.stdin_data = stdin_data;
+        context.max_output_size = ROOT_CONFIG.get_config().hooks.max_hook_output_size;
+        let config_dir = hook.src.parent().with_context(
         || format!("Could not find parent directory for working directory of command execution for hook defined in configuration file {:?}",
             hook.src
         )
-    )?.to_path_buf());
-        context.description = Some(format!("from {:?}", hook.src));
+    )?;
+        context.workdir = Some(match &hook.working_dir {
+            Some(working_dir) => config_dir.join(working_dir).clean_path()?,
+            None => config_dir.to_path_buf(),
+        });
+        context.description = Some(match &hook.description {
+            Some(description) => description.clone(),
+            None => format!("from {:?}", hook.src),
+        });
 
         // Add file context environment variables if provided
         if let Some((src, dest)) = file_context {
@@ -193,8 +354,10 @@ impl HookStrategy {
             ));
         }
 
-        execute_command(&hook.command, &context)?;
-        Ok(())
+        // Add environment captured from previous hooks
+        context.env_vars.extend(extra_env.iter().cloned());
+
+        execute_command(&hook.command, &context)
     }
 
     /// Execute a file-specific hook
@@ -211,6 +374,7 @@ impl HookStrategy {
         }
 
         let mut context = CommandContext::default();
+        context.max_output_size = ROOT_CONFIG.get_config().hooks.max_hook_output_size;
         context.env_vars.push((
             "TYPEWRITER_FILE_SRC".to_string(),
             src.to_string_lossy().to_string(),
@@ -222,7 +386,7 @@ impl HookStrategy {
         context.description = Some(format!("file hook from {:?}", src_config));
 
         if let Err(e) = execute_command(command, &context) {
-            self.handle_hook_error(command, src_config, e, continue_on_error)?;
+            Self::handle_hook_error(command, src_config, e, continue_on_error)?;
         }
 
         Ok(())
@@ -230,7 +394,6 @@ impl HookStrategy {
 
     /// Handle hook execution error based on strategy
     fn handle_hook_error(
-        &self,
         command: &str,
         src: &Path,
         error: anyhow::Error,
@@ -240,7 +403,7 @@ impl HookStrategy {
 
         // Per-hook override takes precedence
         if continue_on_error {
-            warn!("Continuing despite hook failure (continue_on_error=true)");
+            typewriter_warn!("Continuing despite hook failure (continue_on_error=true)");
             return Ok(());
         }
 
@@ -250,7 +413,7 @@ impl HookStrategy {
                 bail!("Aborting apply operation due to hook failure");
             }
             FailureStrategy::Continue => {
-                warn!("Continuing despite hook failure");
+                typewriter_warn!("Continuing despite hook failure");
                 Ok(())
             }
         }
@@ -258,6 +421,22 @@ impl HookStrategy {
 }
 
 impl ApplyStrategy for HookStrategy {
+    fn name(&self) -> &'static str {
+        "hooks"
+    }
+
+    fn variant(&self) -> String {
+        if !ROOT_CONFIG.get_config().hooks.hooks_enabled {
+            return String::from("Disabled");
+        }
+
+        format!(
+            "{} pre_apply, {} post_apply hooks",
+            self.pre_apply_hooks.len(),
+            self.post_apply_hooks.len()
+        )
+    }
+
     fn run_before_apply(&self, _files: &mut TrackedFileList) -> Result<()> {
         info!(
             "Executing pre_apply hooks ({} hooks)",
@@ -301,4 +480,12 @@ impl ApplyStrategy for HookStrategy {
         );
         self.execute_stage_hooks(&self.post_apply_hooks)
     }
+
+    fn run_after_rollback(&self, reason: &str) -> Result<()> {
+        info!(
+            "Executing on_rollback hooks ({} hooks)",
+            self.rollback_hooks.len()
+        );
+        self.execute_rollback_hooks(reason)
+    }
 }
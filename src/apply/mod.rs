@@ -1,16 +1,27 @@
-use std::path::PathBuf;
+use std::{collections::HashMap, fs, path::PathBuf, time::Instant};
+
+use log::info;
+use rayon::prelude::*;
+use xxhash_rust::xxh3::xxh3_64;
 
 use ansi_term::Color::{Black, White};
-use serde::Deserialize;
+use serde::{Deserialize, Serialize, de};
 
 use crate::{
     apply::{
-        checkdiff::FileCheckDiffStrategy, fileperm::FilePermissionStrategy,
-        strategy::ApplyStrategy, tempcopy::TemporaryCopyStrategy,
+        checkdiff::FileCheckDiffStrategy,
+        events::{ApplyEvent, publish_event},
+        fileperm::FilePermissionStrategy,
+        strategy::{ApplyStrategy, DebugWrappedStrategy},
+        tempcopy::{TemporaryCopyStrategy, restore_skipped_file},
     },
-    file::TrackedFileList,
+    config::ROOT_CONFIG,
+    file::{TrackedFile, TrackedFileList},
+    log::paint,
 };
 
+use self::variables::VariableApplying;
+
 // Strategy trait for dyn handling
 pub mod strategy;
 
@@ -29,9 +40,39 @@ pub mod hooks;
 // File permission checking
 pub mod fileperm;
 
+// Checkpointing completed files for resuming
+pub mod checkpoint;
+
+// History of when files were last applied
+pub mod history;
+
+// Reading and writing the files kept in the metadata directory
+pub mod store;
+
+// Time spent in each stage of the last apply
+pub mod timings;
+
+// Outcome of every file of the last apply
+pub mod results;
+
+// Machine-readable reports of an apply
+pub mod report;
+
+// Comparing configurations with apply --config-diff
+pub mod config_diff;
+
+// Publishing events of an apply to a socket
+pub mod events;
+
+// Atomically writing destination files
+pub mod atomic;
+
+// Preserving permissions and ownership of destination files
+pub mod preserve;
+
 /// Configuration options to apply command
 /// files
-#[derive(Deserialize, Debug)]
+#[derive(Deserialize, Serialize, Debug)]
 #[serde(deny_unknown_fields)]
 pub struct Apply {
     // Whether or not to automatically
@@ -65,6 +106,11 @@ pub struct Apply {
     #[serde(default = "default_is_true")]
     pub cleanup_files: bool,
 
+    // Keep this many of the most recent temporary copies
+    // of every destination when cleaning up instead of none
+    #[serde(default)]
+    pub keep_temp_copies: Option<usize>,
+
     // Name of the checkdiff storage file for
     // checkdiff in the metadata path
     #[serde(default = "default_checkdiff_file_name")]
@@ -84,6 +130,12 @@ pub struct Apply {
     #[serde(default = "default_is_true")]
     pub checkdiff_skip_same: bool,
 
+    // Also store an xxhash with the modification time
+    // for the mtime checkdiff strategy, checked if the
+    // modification time of a destination changed
+    #[serde(default)]
+    pub mtime_fallback_hash: bool,
+
     // Skip prompting for confirmation if the entry is new to the checkdiff file
     // and the checkdiff file was already initialised
     //
@@ -95,6 +147,12 @@ pub struct Apply {
     #[serde(default)]
     pub skip_checkdiff_new: bool,
 
+    // Overwrite destinations without prompting when checkdiff
+    // finds they were changed since the last apply, or
+    // has no checksum stored for them to compare against
+    #[serde(default)]
+    pub overwrite_changed: bool,
+
     // Strategy for checking file permissions and
     // optionally creating missing destination files
     #[serde(default)]
@@ -104,6 +162,84 @@ pub struct Apply {
     // when file_permission_strategy is set to create_if_missing
     #[serde(default = "default_is_true")]
     pub auto_confirm_file_creation: bool,
+
+    // Format of the line printed for every applied file
+    // replacing the default output if set
+    //
+    // Format specifiers:
+    // {status} - Status of the file (APPLIED)
+    // {source} - Source file path
+    // {destination} - Destination file path
+    // {ref} - Configuration file referencing the file
+    // {size} - Size of the destination file in bytes
+    // {vars_replaced} - Amount of variables substituted
+    #[serde(default)]
+    pub apply_output_format: Option<String>,
+
+    // Maximum size of source files in bytes, to not
+    // exhaust memory on accidentally tracked binary files
+    #[serde(default = "default_max_source_file_size")]
+    pub max_source_file_size: u64,
+
+    // Log every phase each strategy runs
+    // for debugging the apply process
+    #[serde(default)]
+    pub strategy_debug: bool,
+
+    // Order the strategies are run in for every
+    // phase of the apply, by their names
+    #[serde(default = "default_apply_strategy_order")]
+    pub apply_strategy_order: Vec<String>,
+
+    // Size in bytes of the buffers used
+    // for reading files while applying
+    #[serde(
+        default = "default_copy_buffer_size",
+        deserialize_with = "deserialize_copy_buffer_size"
+    )]
+    pub copy_buffer_size: usize,
+
+    // Format of the confirmation prompt before
+    // applying, replacing the default if set
+    //
+    // Format specifiers:
+    // {count} - Amount of files to apply
+    // {config_file} - Configuration file being applied
+    // {metadata_dir} - Directory of the apply metadata
+    #[serde(default)]
+    pub confirmation_prompt_format: Option<String>,
+
+    // Message printed before confirming the apply,
+    // with variables substituted into it
+    #[serde(default)]
+    pub pre_apply_message: Option<String>,
+
+    // Message printed after a successful apply,
+    // with variables substituted into it
+    #[serde(default)]
+    pub post_apply_message: Option<String>,
+
+    // Check symlinked destinations still point at the link_target
+    // of their file, since writing through them would modify
+    // the file they point to
+    #[serde(default = "default_is_true")]
+    pub link_verification: bool,
+
+    // Unix domain socket to send JSON events of
+    // the apply to as datagrams while applying
+    #[serde(default)]
+    pub event_socket: Option<PathBuf>,
+
+    // Run the per-file stages of the strategies for
+    // every file at once rather than one at a time
+    #[serde(default)]
+    pub parallel_apply: bool,
+
+    // Write destinations to a temporary file next to them
+    // first and rename it over them, so an interrupted
+    // apply never leaves them partially written
+    #[serde(default = "default_is_true")]
+    pub atomic_write: bool,
 }
 
 /// I think we have to sadly re-duplicate serde default here
@@ -117,12 +253,27 @@ impl Default for Apply {
             temp_copy_strategy: Default::default(),
             temp_copy_path_delim: default_temp_copy_path_delim(),
             cleanup_files: default_is_true(),
+            keep_temp_copies: None,
             checkdiff_file_name: default_checkdiff_file_name(),
             checkdiff_strategy: Default::default(),
             skip_checkdiff_new: Default::default(),
+            overwrite_changed: Default::default(),
             checkdiff_skip_same: default_is_true(),
+            mtime_fallback_hash: Default::default(),
             file_permission_strategy: Default::default(),
             auto_confirm_file_creation: default_is_true(),
+            apply_output_format: None,
+            max_source_file_size: default_max_source_file_size(),
+            strategy_debug: Default::default(),
+            apply_strategy_order: default_apply_strategy_order(),
+            copy_buffer_size: default_copy_buffer_size(),
+            confirmation_prompt_format: None,
+            pre_apply_message: None,
+            post_apply_message: None,
+            link_verification: default_is_true(),
+            event_socket: None,
+            parallel_apply: Default::default(),
+            atomic_write: default_is_true(),
         }
     }
 }
@@ -141,17 +292,229 @@ fn default_temp_copy_path_delim() -> String {
     String::from("-")
 }
 
+/// Default maximum size of source files (10 MB)
+fn default_max_source_file_size() -> u64 {
+    10 * 1024 * 1024
+}
+
+/// Default size of the buffers for reading files (64 KB)
+fn default_copy_buffer_size() -> usize {
+    65536
+}
+
+/// Special deserialize for the copy buffer size, since
+/// an empty buffer reads every file as if it were empty.
+fn deserialize_copy_buffer_size<'de, D>(deserializer: D) -> Result<usize, D::Error>
+where
+    D: de::Deserializer<'de>,
+{
+    let copy_buffer_size = usize::deserialize(deserializer)?;
+
+    if copy_buffer_size == 0 {
+        return Err("copy_buffer_size has to be greater than 0").map_err(de::Error::custom);
+    }
+
+    Ok(copy_buffer_size)
+}
+
+/// Default order of the apply strategies
+fn default_apply_strategy_order() -> Vec<String> {
+    [
+        "file_permissions",
+        "variables",
+        "checkdiff",
+        "temp_copy",
+        "hooks",
+    ]
+    .into_iter()
+    .map(String::from)
+    .collect()
+}
+
 /// Default directory for tempfiles
 fn default_tempfile_dir() -> PathBuf {
     PathBuf::from(".typewriter")
 }
 
+/// Error of a single file which failed to apply
+#[derive(Debug)]
+pub struct ApplyError {
+    // Destination of the file which failed
+    pub destination: PathBuf,
+
+    // What went wrong while applying it
+    pub error: anyhow::Error,
+}
+
+/// What happened to a single file in an apply
+#[derive(Serialize, Debug)]
+pub enum FileApplyStatus {
+    // Fully applied to its destination
+    Applied,
+
+    // Skipped by one of the strategies before applying
+    Skipped { reason: String },
+
+    // Failed to apply, only when auto_skip_unable_apply is enabled
+    Failed { error: String },
+}
+
+/// Outcome of an apply for a single file
+#[derive(Serialize, Debug)]
+pub struct FileApplyResult {
+    // Source of the file
+    pub file: PathBuf,
+
+    // Destination of the file
+    pub destination: PathBuf,
+
+    // What happened to the file
+    pub status: FileApplyStatus,
+
+    // Milliseconds spent applying the file itself, not
+    // including the stages which run for all files at once
+    pub duration_ms: u64,
+}
+
+/// Outcome of an apply over all of its files
+#[derive(Debug, Default)]
+pub struct ApplyReport {
+    // Destinations of files which were fully applied
+    pub applied: Vec<PathBuf>,
+
+    // Destinations of files which were skipped by the strategies
+    pub skipped: Vec<PathBuf>,
+
+    // Files which failed to apply, only collected
+    // when auto_skip_unable_apply is enabled
+    pub errors: Vec<ApplyError>,
+
+    // Milliseconds spent in each stage of each strategy,
+    // keyed by "<strategy>.<stage>" e.g "hooks.after_apply"
+    pub stage_timings: HashMap<String, u64>,
+
+    // Outcome of every file, in the order they were applied
+    pub results: Vec<FileApplyResult>,
+}
+
+/// Returns the metadata directory of the provided file, the one of
+/// its configuration file if it set one, else the apply_metadata_dir
+pub fn file_metadata_dir(file: &TrackedFile) -> &PathBuf {
+    file.metadata_dir
+        .as_ref()
+        .unwrap_or(&ROOT_CONFIG.get_config().apply.apply_metadata_dir)
+}
+
+/// Returns the metadata directories of all of the provided files,
+/// always including the apply_metadata_dir, without duplicates
+pub fn metadata_dirs(files: &TrackedFileList) -> Vec<PathBuf> {
+    let mut metadata_dirs = vec![ROOT_CONFIG.get_config().apply.apply_metadata_dir.clone()];
+
+    for file in files.iter() {
+        let metadata_dir = file_metadata_dir(file);
+        if !metadata_dirs.contains(metadata_dir) {
+            metadata_dirs.push(metadata_dir.clone());
+        }
+    }
+
+    metadata_dirs
+}
+
+/// Returns the metadata directory of the file of every destination
+pub fn destination_metadata_dirs(files: &TrackedFileList) -> HashMap<PathBuf, PathBuf> {
+    files
+        .iter()
+        .map(|file| (file.destination.clone(), file_metadata_dir(file).clone()))
+        .collect()
+}
+
+/// Groups the destinations by the metadata directory of their file,
+/// the apply_metadata_dir for destinations without a known file
+pub fn group_by_metadata_dir(
+    destinations: &[PathBuf],
+    destination_dirs: &HashMap<PathBuf, PathBuf>,
+) -> HashMap<PathBuf, Vec<PathBuf>> {
+    let mut grouped: HashMap<PathBuf, Vec<PathBuf>> = HashMap::new();
+
+    for destination in destinations {
+        let metadata_dir = destination_dirs
+            .get(destination)
+            .unwrap_or(&ROOT_CONFIG.get_config().apply.apply_metadata_dir);
+
+        grouped
+            .entry(metadata_dir.clone())
+            .or_default()
+            .push(destination.clone());
+    }
+
+    grouped
+}
+
 /// Run apply copy with atomicity and transactional behavior
 pub fn apply(
     mut files: TrackedFileList,
     strategies: Vec<&dyn ApplyStrategy>,
-) -> anyhow::Result<()> {
-    let result = run_apply_strategies(&mut files, &strategies);
+) -> anyhow::Result<ApplyReport> {
+    // Log every phase of the strategies if debugging them
+    let debug_strategies: Vec<DebugWrappedStrategy> = strategies
+        .iter()
+        .map(|strategy| DebugWrappedStrategy(*strategy))
+        .collect();
+    let strategies: Vec<&dyn ApplyStrategy> = if ROOT_CONFIG.get_config().apply.strategy_debug {
+        debug_strategies
+            .iter()
+            .map(|strategy| strategy as &dyn ApplyStrategy)
+            .collect()
+    } else {
+        strategies
+    };
+
+    if ROOT_CONFIG.get_config().apply.parallel_apply && !runs_in_parallel() {
+        log::warn!(
+            "Applying files one at a time despite parallel_apply, since confirm_shell_commands would prompt for per-file hooks at the same time"
+        );
+    }
+
+    // Completed files are checkpointed in the metadata directory of their file
+    let destination_dirs = destination_metadata_dirs(&files);
+
+    let mut report = ApplyReport::default();
+    let result = run_apply_strategies(&mut files, &strategies, &mut report);
+
+    // Show where the time of the apply went when debugging strategies
+    if ROOT_CONFIG.get_config().apply.strategy_debug {
+        let mut stage_timings: Vec<_> = report.stage_timings.iter().collect();
+        stage_timings.sort();
+        for (stage, millis) in stage_timings {
+            log::debug!("[strategy] {} took {}ms", stage, millis);
+        }
+    }
+
+    // Keep the timings around for looking into slow applies later
+    if let Err(timings_err) = timings::record_stage_timings(&report.stage_timings) {
+        log::error!("Failed to save apply stage timings: {:?}", timings_err);
+    }
+
+    // Same for what happened to every file
+    if let Err(results_err) = results::record_results(&report.results) {
+        log::error!("Failed to save apply results: {:?}", results_err);
+    }
+
+    // Files which are restored from their temporary copies on failure
+    // aren't completed anymore, so resuming has to apply them again
+    let rolled_back = result.is_err()
+        && !matches!(
+            ROOT_CONFIG.get_config().apply.temp_copy_strategy,
+            TemporaryCopyStrategy::Disabled
+        );
+
+    // Keep track of completed files for resuming later
+    if (result.is_err() || !report.errors.is_empty()) && !rolled_back {
+        if let Err(checkpoint_err) = checkpoint::save_checkpoint(&report.applied, &destination_dirs)
+        {
+            log::error!("Failed to save apply checkpoint: {:?}", checkpoint_err);
+        }
+    }
 
     if let Err(e) = result {
         log::error!("Apply operation failed, initiating rollback");
@@ -159,42 +522,334 @@ pub fn apply(
         for strategy in strategies.iter().rev() {
             let _ = strategy.run_on_failure(&mut files);
         }
+
+        let reason = format!("{:#}", e);
+        for strategy in strategies.iter() {
+            if let Err(rollback_err) = strategy.run_after_rollback(&reason) {
+                log::error!(
+                    "Failed to run {} after rolling back: {:?}",
+                    strategy.name(),
+                    rollback_err
+                );
+            }
+        }
         return Err(e);
     }
 
-    Ok(())
+    publish_event(&ApplyEvent::ApplyComplete {
+        count: report.applied.len(),
+    });
+    Ok(report)
 }
 
-fn run_apply_strategies(
+/// Skips files whose rendered source content is the same as
+/// the checksum stored for their destination by the last apply,
+/// before any of the strategies run for them.
+pub fn skip_unchanged_files(
     files: &mut TrackedFileList,
+    var_strategy: &VariableApplying,
     strategies: &[&dyn ApplyStrategy],
 ) -> anyhow::Result<()> {
-    for strategy in strategies {
-        strategy.run_before_apply(files)?;
-    }
+    let checkdiff_strategy = &ROOT_CONFIG.get_config().apply.checkdiff_strategy;
+    let checksum_entries = FileCheckDiffStrategy::read_checksum_entries(&metadata_dirs(files))?;
+
+    let mut changed_files = Vec::new();
+    for mut file in files.0.drain(..) {
+        let is_unchanged = match (
+            checksum_entries.entries.get(&file.destination),
+            checkdiff_strategy,
+        ) {
+            (_, FileCheckDiffStrategy::Disabled) | (None, _) => false,
+            (Some(stored_hash), _) => FileCheckDiffStrategy::bytes_match_stored(
+                stored_hash,
+                &var_strategy.render_file(&file)?,
+            ),
+        };
+
+        if !is_unchanged {
+            changed_files.push(file);
+            continue;
+        }
+
+        info!(
+            "Skipping file {:?} that would apply to {:?} referenced by config {:?} since it is unchanged since the last apply",
+            file.file, file.destination, file.src
+        );
 
-    for file in &mut files.0 {
         for strategy in strategies {
-            strategy.run_before_apply_file(file)?;
+            strategy.run_on_skip(&mut file)?;
         }
     }
 
-    for file in &mut files.0 {
-        for strategy in strategies {
-            strategy.run_after_apply_file(file)?;
-        }
+    files.0 = changed_files;
+    Ok(())
+}
 
+/// Prints the line for an applied file, using the
+/// apply_output_format if one was configured.
+fn print_applied_file(file: &TrackedFile) {
+    let Some(format) = &ROOT_CONFIG.get_config().apply.apply_output_format else {
         println!(
-            "[{}] {:?} to {:?} {}",
-            White.bold().paint("APPLIED"),
+            "[{}] {:?} to {:?} {} {}",
+            paint(White.bold(), "APPLIED"),
             file.file,
             file.destination,
-            Black.dimmed().paint(format!("[ref: {:?}]", file.src))
+            paint(Black.dimmed(), format!("[ref: {:?}]", file.src)),
+            paint(
+                Black.dimmed(),
+                format!("[vars: {}]", file.substitution_count)
+            )
         );
+        return;
+    };
+
+    // Size of the destination after applying
+    let size = fs::metadata(&file.destination)
+        .map(|metadata| metadata.len())
+        .unwrap_or_default();
+
+    println!(
+        "{}",
+        format
+            .replace("{status}", "APPLIED")
+            .replace("{source}", &format!("{:?}", file.file))
+            .replace("{destination}", &format!("{:?}", file.destination))
+            .replace("{ref}", &format!("{:?}", file.src))
+            .replace("{size}", &size.to_string())
+            .replace("{vars_replaced}", &file.substitution_count.to_string())
+    );
+}
+
+/// Records a file which failed a per-file stage in the report, removing
+/// it from the list if auto_skip_unable_apply is enabled so the rest
+/// of the apply continues without it, erroring otherwise.
+fn skip_failed_file(
+    files: &mut TrackedFileList,
+    index: usize,
+    error: anyhow::Error,
+    report: &mut ApplyReport,
+) -> anyhow::Result<()> {
+    if !ROOT_CONFIG.get_config().apply.auto_skip_unable_apply {
+        return Err(error);
     }
 
+    let file = files.0.remove(index);
+    log::error!(
+        "Failed to apply {:?} to {:?} referenced by config {:?}, skipping it: {:?}",
+        file.file,
+        file.destination,
+        file.src,
+        error
+    );
+
+    // The destination may already be (partly) written, and it
+    // won't be rolled back with the rest of the files anymore
+    if let Err(restore_err) = restore_skipped_file(&file) {
+        log::error!(
+            "Failed to restore skipped file {:?} from backup: {:?}",
+            file.destination,
+            restore_err
+        );
+    }
+    report.errors.push(ApplyError {
+        destination: file.destination,
+        error,
+    });
+
+    Ok(())
+}
+
+/// Whether the per-file stages run for every file at once, which they
+/// can't if per-file hooks prompt to confirm their commands, since
+/// all of their prompts would be shown at the same time.
+fn runs_in_parallel() -> bool {
+    let config = ROOT_CONFIG.get_config();
+    config.apply.parallel_apply && !config.commands.confirm_shell_commands
+}
+
+/// Runs a single per-file stage for every file, adding the time each
+/// file took onto durations and running on_success for every file the
+/// stage succeeded for. Files which fail are handled by skip_failed_file.
+///
+/// With parallel_apply the stage runs for every file at once, after
+/// which the results are handled in order of the files.
+fn run_file_stage(
+    files: &mut TrackedFileList,
+    report: &mut ApplyReport,
+    durations: &mut HashMap<PathBuf, u64>,
+    stage: impl Fn(&mut TrackedFile, &mut HashMap<String, u64>) -> anyhow::Result<()> + Sync,
+    mut on_success: impl FnMut(&TrackedFile, &mut ApplyReport),
+) -> anyhow::Result<()> {
+    // Runs the stage for one file with its own stage timings
+    let run_stage = |file: &mut TrackedFile| {
+        let mut stage_timings = HashMap::new();
+        let start = Instant::now();
+        let result = stage(file, &mut stage_timings);
+        (result, start.elapsed().as_millis() as u64, stage_timings)
+    };
+
+    let outcomes: Vec<_> = if runs_in_parallel() {
+        files.0.par_iter_mut().map(run_stage).collect()
+    } else {
+        Vec::new()
+    };
+    let mut outcomes = outcomes.into_iter();
+
+    let mut index = 0;
+    while index < files.0.len() {
+        // Serially the stage runs for the next file only now, so
+        // a failure stops the rest of the files from running it.
+        let (result, millis, stage_timings) = match outcomes.next() {
+            Some(outcome) => outcome,
+            None => run_stage(&mut files.0[index]),
+        };
+
+        *durations
+            .entry(files.0[index].destination.clone())
+            .or_default() += millis;
+        for (stage, millis) in stage_timings {
+            *report.stage_timings.entry(stage).or_default() += millis;
+        }
+
+        match result {
+            Ok(()) => {
+                on_success(&files.0[index], report);
+                index += 1;
+            }
+            Err(e) => skip_failed_file(files, index, e, report)?,
+        }
+    }
+
+    Ok(())
+}
+
+/// Runs a single stage of a strategy, adding the time
+/// it took onto the total for that stage.
+fn time_stage(
+    stage_timings: &mut HashMap<String, u64>,
+    strategy: &dyn ApplyStrategy,
+    stage: &str,
+    run: impl FnOnce() -> anyhow::Result<()>,
+) -> anyhow::Result<()> {
+    let start = Instant::now();
+    let result = run();
+
+    *stage_timings
+        .entry(format!("{}.{}", strategy.name(), stage))
+        .or_default() += start.elapsed().as_millis() as u64;
+
+    result
+}
+
+fn run_apply_strategies(
+    files: &mut TrackedFileList,
+    strategies: &[&dyn ApplyStrategy],
+    report: &mut ApplyReport,
+) -> anyhow::Result<()> {
+    // Stable ordering across machines and runs, by the hash
+    // of the destination rather than the order of parsing.
+    files.sort_by_cached_key(|file| xxh3_64(file.destination.to_string_lossy().as_bytes()));
+
+    // Files before any strategy has a chance to skip them
+    let sources: Vec<(PathBuf, PathBuf)> = files
+        .iter()
+        .map(|file| (file.file.clone(), file.destination.clone()))
+        .collect();
+
+    // Which strategy skipped each skipped file
+    let mut skip_reasons: HashMap<PathBuf, String> = HashMap::new();
+
     for strategy in strategies {
-        strategy.run_after_apply(files)?;
+        let before: Vec<PathBuf> = files.iter().map(|file| file.destination.clone()).collect();
+
+        time_stage(&mut report.stage_timings, *strategy, "before_apply", || {
+            strategy.run_before_apply(files)
+        })?;
+
+        for destination in before {
+            if !files.iter().any(|file| file.destination == destination) {
+                skip_reasons.insert(destination, format!("skipped by {}", strategy.name()));
+            }
+        }
+    }
+
+    report.skipped = sources
+        .iter()
+        .map(|(_, destination)| destination)
+        .filter(|destination| skip_reasons.contains_key(*destination))
+        .cloned()
+        .collect();
+
+    // Time spent applying each file
+    let mut durations: HashMap<PathBuf, u64> = HashMap::new();
+
+    run_file_stage(
+        files,
+        report,
+        &mut durations,
+        |file, stage_timings| {
+            for strategy in strategies {
+                time_stage(stage_timings, *strategy, "before_apply_file", || {
+                    strategy.run_before_apply_file(file)
+                })?;
+            }
+            Ok(())
+        },
+        |_, _| {},
+    )?;
+
+    run_file_stage(
+        files,
+        report,
+        &mut durations,
+        |file, stage_timings| {
+            for strategy in strategies {
+                time_stage(stage_timings, *strategy, "after_apply_file", || {
+                    strategy.run_after_apply_file(file)
+                })?;
+            }
+            Ok(())
+        },
+        |file, report| {
+            print_applied_file(file);
+            publish_event(&ApplyEvent::FileApplied {
+                destination: file.destination.clone(),
+            });
+
+            report.applied.push(file.destination.clone());
+        },
+    )?;
+
+    for strategy in strategies {
+        time_stage(&mut report.stage_timings, *strategy, "after_apply", || {
+            strategy.run_after_apply(files)
+        })?;
+    }
+
+    // Outcome of every file, in the order they were applied
+    for (file, destination) in sources {
+        let status = if report.applied.contains(&destination) {
+            FileApplyStatus::Applied
+        } else if let Some(apply_error) = report
+            .errors
+            .iter()
+            .find(|apply_error| apply_error.destination == destination)
+        {
+            FileApplyStatus::Failed {
+                error: format!("{:#}", apply_error.error),
+            }
+        } else if let Some(reason) = skip_reasons.remove(&destination) {
+            FileApplyStatus::Skipped { reason }
+        } else {
+            continue;
+        };
+
+        report.results.push(FileApplyResult {
+            duration_ms: durations.get(&destination).copied().unwrap_or_default(),
+            file,
+            destination,
+            status,
+        });
     }
 
     Ok(())
@@ -0,0 +1,143 @@
+//! Keeping the permission mode and ownership of destination
+//! files the same after they are written by an apply
+
+#[cfg(unix)]
+use std::fs;
+
+#[cfg(unix)]
+use anyhow::Context;
+#[cfg(unix)]
+use log::warn;
+
+use crate::file::TrackedFile;
+
+/// Permission mode and ownership to give a destination
+/// once it has been written
+#[cfg_attr(not(unix), allow(dead_code))]
+pub struct PreservedPermissions {
+    // Permission mode to set on the destination
+    mode: Option<u32>,
+
+    // Owner and group of the destination, only
+    // kept if it existed before it was written
+    owner: Option<(u32, u32)>,
+}
+
+impl PreservedPermissions {
+    /// Reads the permissions to give the destination of the file after it
+    /// is written, the explicit mode of the file if it has one, otherwise
+    /// those of the current destination, or the source if there is none yet.
+    #[cfg(unix)]
+    pub fn capture(file: &TrackedFile) -> anyhow::Result<Self> {
+        use std::os::unix::fs::MetadataExt;
+
+        if !file.preserve_permissions {
+            return Ok(Self {
+                mode: file.mode,
+                owner: None,
+            });
+        }
+
+        if let Ok(metadata) = fs::metadata(&file.destination) {
+            return Ok(Self {
+                mode: file.mode.or(Some(metadata.mode() & 0o7777)),
+                owner: Some((metadata.uid(), metadata.gid())),
+            });
+        }
+
+        let metadata = fs::metadata(&file.file).with_context(|| {
+            format!(
+                "While reading the permissions of file {:?} referenced in configuration file {:?}",
+                file.file, file.src
+            )
+        })?;
+
+        Ok(Self {
+            mode: file.mode.or(Some(metadata.mode() & 0o7777)),
+            owner: None,
+        })
+    }
+
+    /// Permissions are unix-only, so nothing is preserved elsewhere.
+    #[cfg(not(unix))]
+    pub fn capture(_file: &TrackedFile) -> anyhow::Result<Self> {
+        Ok(Self {
+            mode: None,
+            owner: None,
+        })
+    }
+
+    /// Sets the captured permissions on the destination of the file.
+    ///
+    /// Changing the owner is usually only permitted as root, so failing
+    /// to restore it is warned about rather than failing the apply.
+    #[cfg(unix)]
+    pub fn restore(self: &Self, file: &TrackedFile) -> anyhow::Result<()> {
+        use std::os::unix::fs::{MetadataExt, PermissionsExt};
+
+        if let Some(mode) = self.mode {
+            fs::set_permissions(&file.destination, fs::Permissions::from_mode(mode))
+                .with_context(|| {
+                    format!(
+                        "While setting mode {:o} on destination file {:?} referenced in configuration file {:?}",
+                        mode, file.destination, file.src
+                    )
+                })?;
+        }
+
+        let Some((uid, gid)) = self.owner else {
+            return Ok(());
+        };
+
+        // Written in place the owner never changes, only
+        // a replaced destination needs it set again
+        let metadata = fs::metadata(&file.destination)?;
+        if metadata.uid() == uid && metadata.gid() == gid {
+            return Ok(());
+        }
+
+        if let Err(e) = std::os::unix::fs::chown(&file.destination, Some(uid), Some(gid)) {
+            warn!(
+                "Could not restore owner {}:{} of destination file {:?} referenced in configuration file {:?}: {}",
+                uid, gid, file.destination, file.src, e
+            );
+        }
+
+        Ok(())
+    }
+
+    /// Permissions are unix-only, so nothing is restored elsewhere.
+    #[cfg(not(unix))]
+    pub fn restore(self: &Self, _file: &TrackedFile) -> anyhow::Result<()> {
+        Ok(())
+    }
+}
+
+/// Gives a newly created destination the permission mode of its source,
+/// so it is preserved as the mode of the destination when it is written.
+#[cfg(unix)]
+pub fn copy_source_permissions(file: &TrackedFile) -> anyhow::Result<()> {
+    if !file.preserve_permissions {
+        return Ok(());
+    }
+
+    let metadata = fs::metadata(&file.file).with_context(|| {
+        format!(
+            "While reading the permissions of file {:?} referenced in configuration file {:?}",
+            file.file, file.src
+        )
+    })?;
+
+    fs::set_permissions(&file.destination, metadata.permissions()).with_context(|| {
+        format!(
+            "While setting the permissions of {:?} on destination file {:?}",
+            file.file, file.destination
+        )
+    })
+}
+
+/// Permissions are unix-only, so nothing is copied elsewhere.
+#[cfg(not(unix))]
+pub fn copy_source_permissions(_file: &TrackedFile) -> anyhow::Result<()> {
+    Ok(())
+}
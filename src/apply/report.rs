@@ -0,0 +1,116 @@
+//! Machine-readable report of what an apply
+//! would do, without applying anything
+
+use std::{fs, path::PathBuf};
+
+use anyhow::Context;
+use serde::Serialize;
+use similar::{ChangeTag, TextDiff};
+
+use crate::{
+    apply::{hooks::HookStrategy, variables::VariableApplying},
+    config::ROOT_CONFIG,
+    file::{TrackedFile, TrackedFileList},
+};
+
+/// Report of what applying a single file would do
+#[derive(Serialize, Debug)]
+struct FileReport {
+    source: PathBuf,
+    destination: PathBuf,
+    would_change: bool,
+    diff_lines_added: usize,
+    diff_lines_removed: usize,
+    variables_to_substitute: Vec<String>,
+    hooks_would_run: Vec<String>,
+}
+
+/// Report of what the entire apply would do
+#[derive(Serialize, Debug)]
+struct DryRunReport {
+    files: Vec<FileReport>,
+    hooks_would_run: Vec<String>,
+}
+
+/// Builds the report of a single file by rendering it
+/// in memory and comparing it with the current destination
+fn report_file(file: &TrackedFile, var_strategy: &VariableApplying) -> anyhow::Result<FileReport> {
+    let rendered = var_strategy.render_file(file)?;
+
+    // Destinations which don't exist yet are compared as empty
+    let current = if file.destination.exists() {
+        fs::read(&file.destination).with_context(|| {
+            format!(
+                "While trying to read destination {:?} referenced in configuration file {:?}",
+                file.destination, file.src
+            )
+        })?
+    } else {
+        Vec::new()
+    };
+
+    // Count changed lines
+    let rendered_text = String::from_utf8_lossy(&rendered);
+    let current_text = String::from_utf8_lossy(&current);
+    let diff = TextDiff::from_lines(current_text.as_ref(), rendered_text.as_ref());
+
+    let mut diff_lines_added = 0;
+    let mut diff_lines_removed = 0;
+    for change in diff.iter_all_changes() {
+        match change.tag() {
+            ChangeTag::Insert => diff_lines_added += 1,
+            ChangeTag::Delete => diff_lines_removed += 1,
+            ChangeTag::Equal => {}
+        }
+    }
+
+    let hooks_would_run = if ROOT_CONFIG.get_config().hooks.hooks_enabled {
+        file.pre_hook
+            .iter()
+            .chain(file.post_hook.iter())
+            .cloned()
+            .collect()
+    } else {
+        Vec::new()
+    };
+
+    Ok(FileReport {
+        source: file.file.clone(),
+        destination: file.destination.clone(),
+        would_change: rendered != current,
+        diff_lines_added,
+        diff_lines_removed,
+        variables_to_substitute: var_strategy.file_variable_names(file)?,
+        hooks_would_run,
+    })
+}
+
+/// Writes a JSON report of what applying the files would do to path
+pub fn write_dry_run_report(
+    path: &PathBuf,
+    files: &TrackedFileList,
+    var_strategy: &VariableApplying,
+    hook_strategy: &HookStrategy,
+) -> anyhow::Result<()> {
+    let hooks_would_run = if ROOT_CONFIG.get_config().hooks.hooks_enabled {
+        hook_strategy.global_hook_commands()
+    } else {
+        Vec::new()
+    };
+
+    let report = DryRunReport {
+        files: files
+            .iter()
+            .map(|file| report_file(file, var_strategy))
+            .collect::<anyhow::Result<_>>()?,
+        hooks_would_run,
+    };
+
+    let report_string = serde_json::to_string_pretty(&report)
+        .with_context(|| "While trying to serialize dry run report")?;
+
+    fs::write(path, report_string)
+        .with_context(|| format!("While trying to write dry run report {:?}", path))?;
+
+    Ok(())
+}
@@ -0,0 +1,52 @@
+//! Outcome of every file of the last apply, for
+//! looking into what an apply did after the fact
+
+use std::{
+    path::PathBuf,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use serde::Serialize;
+
+use crate::{
+    apply::{FileApplyResult, store::write_ron},
+    cleanpath::CleanPath,
+    config::ROOT_CONFIG,
+};
+
+/// Name of the results file in the metadata directory
+const RESULTS_FILE_NAME: &str = ".apply_results";
+
+/// Results of the last apply stored in the metadata directory
+#[derive(Serialize, Debug)]
+struct ApplyResults<'a> {
+    // Unix timestamp (seconds) of when the apply finished
+    recorded_at: i64,
+
+    // Outcome of every file, in the order they were applied
+    results: &'a [FileApplyResult],
+}
+
+/// Returns the file path to the results
+/// file in the metadata directory
+fn get_results_file_path() -> anyhow::Result<PathBuf> {
+    ROOT_CONFIG
+        .get_config()
+        .apply
+        .apply_metadata_dir
+        .join(RESULTS_FILE_NAME)
+        .clean_path()
+}
+
+/// Records the outcome of every file of an apply,
+/// replacing the ones of the apply before it
+pub fn record_results(results: &[FileApplyResult]) -> anyhow::Result<()> {
+    let path = get_results_file_path()?;
+
+    let apply_results = ApplyResults {
+        recorded_at: SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs() as i64,
+        results,
+    };
+
+    write_ron(&path, &apply_results, "apply results")
+}
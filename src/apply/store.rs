@@ -0,0 +1,44 @@
+//! Reading and writing the RON files
+//! an apply keeps in the metadata directory
+
+use std::{fs, path::PathBuf};
+
+use anyhow::Context;
+use serde::{Serialize, de::DeserializeOwned};
+
+/// Reads and parses the RON file at the path, None if it doesn't
+/// exist yet. The name describes the file in errors.
+pub fn read_ron<T: DeserializeOwned>(path: &PathBuf, name: &str) -> anyhow::Result<Option<T>> {
+    if !path.exists() {
+        return Ok(None);
+    }
+
+    let file_content = fs::read_to_string(path)
+        .with_context(|| format!("While trying to read {} file {:?}", name, path))?;
+
+    let value = ron::from_str(&file_content).with_context(|| {
+        format!(
+            "While trying to parse {} file {:?}, Has it been tampered with?",
+            name, path
+        )
+    })?;
+
+    Ok(Some(value))
+}
+
+/// Writes the value to the RON file at the path, making its
+/// parent directories if needed. The name describes the file in errors.
+pub fn write_ron<T: Serialize>(path: &PathBuf, value: &T, name: &str) -> anyhow::Result<()> {
+    // Make parent directories if it doesn't exist already.
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+
+    let value_string = ron::to_string(value)
+        .with_context(|| format!("While trying to serialize {} file", name))?;
+
+    fs::write(path, value_string)
+        .with_context(|| format!("While trying to write {} file {:?}", name, path))?;
+
+    Ok(())
+}
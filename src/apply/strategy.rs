@@ -1,9 +1,19 @@
 //! Strategy trait for unified strategy-handling
 
+use log::debug;
+
 use crate::file::{TrackedFile, TrackedFileList};
 
-/// Strategy which can be run at multiple stages of the apply stage
-pub trait ApplyStrategy {
+/// Strategy which can be run at multiple stages of the apply stage,
+/// the per-file stages may be ran from multiple threads at once
+/// with parallel_apply so strategies have to be Sync.
+pub trait ApplyStrategy: Sync {
+    /// Name of this strategy for logging and configuration
+    fn name(self: &Self) -> &'static str;
+
+    /// Description of how this strategy is configured
+    fn variant(self: &Self) -> String;
+
     /// This strategy will have this ran
     /// before the overall copy
     fn run_before_apply(self: &Self, files: &mut TrackedFileList) -> anyhow::Result<()> {
@@ -30,10 +40,107 @@ pub trait ApplyStrategy {
         Ok(())
     }
 
+    /// This strategy will be run for a file which
+    /// is skipped before it is applied
+    fn run_on_skip(self: &Self, file: &mut TrackedFile) -> anyhow::Result<()> {
+        let _ = file;
+        Ok(())
+    }
+
     /// This strategy will be run if the apply operation fails
     /// to allow for cleanup or rollback
     fn run_on_failure(self: &Self, files: &mut TrackedFileList) -> anyhow::Result<()> {
         let _ = files;
         Ok(())
     }
+
+    /// This strategy will be run once every strategy has
+    /// rolled back a failed apply, with the error it failed with
+    fn run_after_rollback(self: &Self, reason: &str) -> anyhow::Result<()> {
+        let _ = reason;
+        Ok(())
+    }
+}
+
+/// Wraps a strategy to log every phase it
+/// runs for debugging the apply process
+pub struct DebugWrappedStrategy<'a>(pub &'a dyn ApplyStrategy);
+
+impl DebugWrappedStrategy<'_> {
+    /// Runs a phase of the wrapped strategy, logging
+    /// before and after it for the described target
+    fn run_phase(
+        self: &Self,
+        phase: &str,
+        target: &dyn std::fmt::Debug,
+        run: impl FnOnce() -> anyhow::Result<()>,
+    ) -> anyhow::Result<()> {
+        debug!(
+            "Strategy {} running phase {} for {:?}",
+            self.0.name(),
+            phase,
+            target
+        );
+        let result = run();
+        debug!(
+            "Strategy {} finished phase {} for {:?} (success: {})",
+            self.0.name(),
+            phase,
+            target,
+            result.is_ok()
+        );
+        result
+    }
+}
+
+impl ApplyStrategy for DebugWrappedStrategy<'_> {
+    fn name(self: &Self) -> &'static str {
+        self.0.name()
+    }
+
+    fn variant(self: &Self) -> String {
+        self.0.variant()
+    }
+
+    fn run_before_apply(self: &Self, files: &mut TrackedFileList) -> anyhow::Result<()> {
+        let target = format!("{} files", files.len());
+        self.run_phase("run_before_apply", &target, || {
+            self.0.run_before_apply(files)
+        })
+    }
+
+    fn run_before_apply_file(self: &Self, file: &mut TrackedFile) -> anyhow::Result<()> {
+        let target = file.destination.clone();
+        self.run_phase("run_before_apply_file", &target, || {
+            self.0.run_before_apply_file(file)
+        })
+    }
+
+    fn run_after_apply_file(self: &Self, file: &mut TrackedFile) -> anyhow::Result<()> {
+        let target = file.destination.clone();
+        self.run_phase("run_after_apply_file", &target, || {
+            self.0.run_after_apply_file(file)
+        })
+    }
+
+    fn run_after_apply(self: &Self, files: &mut TrackedFileList) -> anyhow::Result<()> {
+        let target = format!("{} files", files.len());
+        self.run_phase("run_after_apply", &target, || self.0.run_after_apply(files))
+    }
+
+    fn run_on_skip(self: &Self, file: &mut TrackedFile) -> anyhow::Result<()> {
+        let target = file.destination.clone();
+        self.run_phase("run_on_skip", &target, || self.0.run_on_skip(file))
+    }
+
+    fn run_on_failure(self: &Self, files: &mut TrackedFileList) -> anyhow::Result<()> {
+        let target = format!("{} files", files.len());
+        self.run_phase("run_on_failure", &target, || self.0.run_on_failure(files))
+    }
+
+    fn run_after_rollback(self: &Self, reason: &str) -> anyhow::Result<()> {
+        self.run_phase("run_after_rollback", &reason, || {
+            self.0.run_after_rollback(reason)
+        })
+    }
 }
@@ -1,28 +1,41 @@
 //! Responsible for managing the temporary copy component
 //! of the application process
 
-use std::{fs, path::PathBuf};
+use std::{
+    collections::HashMap,
+    fs::{self, File},
+    io,
+    path::PathBuf,
+    time::{SystemTime, UNIX_EPOCH},
+};
 
 use anyhow::Context;
 use log::info;
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
+use similar::TextDiff;
 
 use crate::{
-    apply::strategy::ApplyStrategy,
+    apply::{atomic::write_destination, file_metadata_dir, strategy::ApplyStrategy},
     cleanpath::CleanPath,
     config::ROOT_CONFIG,
     file::{TrackedFile, TrackedFileList},
+    log::typewriter_warn,
 };
 
 /// Which strategy should be used for the temporary
 /// copy stage?
-#[derive(Deserialize, Debug)]
+#[derive(Deserialize, Serialize, Debug)]
 pub enum TemporaryCopyStrategy {
     // Copy all destination files to the temporary directory
     // for backup before proceeding with the operation
     #[serde(rename = "copy_all")]
     CopyAll,
 
+    // Same as copy all, but keep existing temporary copies
+    // younger than max_age_secs rather than copying again
+    #[serde(rename = "copy_recent")]
+    CopyRecent { max_age_secs: u64 },
+
     // Dont do anything for this stage.. No temporary copying
     #[serde(rename = "disabled")]
     Disabled,
@@ -34,6 +47,13 @@ impl Default for TemporaryCopyStrategy {
     }
 }
 
+/// Name of the patch file of the last apply in the metadata directory
+const PATCH_FILE_NAME: &str = "last.patch";
+
+/// Name of the directory rotated temporary copies are kept in, next to the
+/// temporary copies, with a directory of its own for every destination
+pub const ROTATED_DIR_NAME: &str = ".rotated";
+
 pub fn rename_to_temp_copy(path: &PathBuf) -> String {
     path.to_string_lossy()
         .replace("/", &ROOT_CONFIG.get_config().apply.temp_copy_path_delim)
@@ -41,19 +61,30 @@ pub fn rename_to_temp_copy(path: &PathBuf) -> String {
 
 pub fn copy_all_strategy(file: &TrackedFile) -> anyhow::Result<()> {
     // Make tempdir path for this file
-    let mut tempcopy_path = ROOT_CONFIG
-        .get_config()
-        .apply
-        .apply_metadata_dir
-        .clean_path()?;
-
-    fs::create_dir_all(&tempcopy_path)
-        .with_context(|| "While trying to make temporary directory for copying")?;
+    let tempcopy_path = get_temp_copy_path(file)?;
 
-    tempcopy_path.push(rename_to_temp_copy(&file.destination));
+    if let Some(tempcopy_dir) = tempcopy_path.parent() {
+        fs::create_dir_all(tempcopy_dir)
+            .with_context(|| "While trying to make temporary directory for copying")?;
+    }
 
     // Only backup if destination exists
     if !file.destination.exists() {
+        // A copy left by an earlier apply (e.g a backup_destination) isn't of the
+        // current destination, it would be restored or diffed against otherwise
+        if tempcopy_path.exists() {
+            fs::remove_file(&tempcopy_path).with_context(|| {
+                format!(
+                    "While trying to remove stale temporary copy {:?} of {:?}",
+                    tempcopy_path, file.destination
+                )
+            })?;
+            info!(
+                "Removed stale temporary copy {:?} of {:?} which does not exist anymore",
+                tempcopy_path, file.destination
+            );
+        }
+
         info!(
             "Skipping backup of {:?} as it does not exist yet",
             file.destination
@@ -74,15 +105,140 @@ pub fn copy_all_strategy(file: &TrackedFile) -> anyhow::Result<()> {
     Ok(())
 }
 
+/// Copies the destination to the temporary directory unless a temporary copy
+/// of it younger than max_age_secs exists, which the destination has not
+/// been modified since, as restoring it would otherwise lose those changes
+fn copy_recent_strategy(file: &TrackedFile, max_age_secs: u64) -> anyhow::Result<()> {
+    // A missing destination has no recent copy to reuse,
+    // any copy left of it is removed by copy_all_strategy
+    if !file.destination.exists() {
+        return copy_all_strategy(file);
+    }
+
+    let tempcopy_path = get_temp_copy_path(file)?;
+
+    // Time the existing temporary copy was made, if there is one
+    let Ok(backup_modified) = fs::metadata(&tempcopy_path).and_then(|metadata| metadata.modified())
+    else {
+        return copy_all_strategy(file);
+    };
+
+    // Copies are given the time they were made, so a destination modified
+    // after it has changes which the copy doesn't have
+    let destination_modified = fs::metadata(&file.destination)
+        .and_then(|metadata| metadata.modified())
+        .with_context(|| {
+            format!(
+                "While trying to read the modification time of destination file {:?}",
+                file.destination
+            )
+        })?;
+
+    if destination_modified > backup_modified {
+        info!(
+            "Backing up {:?} again as it was modified after temporary copy {:?} was made",
+            file.destination, tempcopy_path
+        );
+        return copy_all_strategy(file);
+    }
+
+    if let Ok(backup_age) = backup_modified.elapsed() {
+        if backup_age.as_secs() < max_age_secs {
+            info!(
+                "Skipping backup of {:?} as temporary copy {:?} is only {} seconds old",
+                file.destination,
+                tempcopy_path,
+                backup_age.as_secs()
+            );
+            return Ok(());
+        }
+    }
+
+    copy_all_strategy(file)
+}
+
+/// Keeps the temporary copy of the file under a timestamped name in a directory
+/// of its own, removing the oldest ones beyond the amount that should be kept.
+fn rotate_temp_copies(file: &TrackedFile, keep_temp_copies: usize) -> anyhow::Result<()> {
+    let tempcopy_path = get_temp_copy_path(file)?;
+    let tempcopy_dir = tempcopy_path
+        .parent()
+        .context("Temporary copy has no parent directory")?;
+    let tempcopy_name = tempcopy_path
+        .file_name()
+        .context("Temporary copy has no file name")?;
+
+    // Only copies of this destination are in here, so no other
+    // destination can be mistaken for one of its rotated copies
+    let rotated_dir = tempcopy_dir.join(ROTATED_DIR_NAME).join(tempcopy_name);
+    fs::create_dir_all(&rotated_dir).with_context(|| {
+        format!(
+            "While trying to make rotated temporary copy directory {:?}",
+            rotated_dir
+        )
+    })?;
+
+    // Timestamp the temporary copy of this apply
+    if tempcopy_path.exists() {
+        let timestamp = SystemTime::now().duration_since(UNIX_EPOCH)?.as_millis();
+        let rotated_path = rotated_dir.join(timestamp.to_string());
+        fs::rename(&tempcopy_path, &rotated_path).with_context(|| {
+            format!(
+                "While trying to rotate temporary copy {:?} to {:?}",
+                tempcopy_path, rotated_path
+            )
+        })?;
+    }
+
+    // All of the rotated temporary copies of this destination
+    let mut rotated_copies: Vec<(u128, PathBuf)> = fs::read_dir(&rotated_dir)
+        .with_context(|| {
+            format!(
+                "While trying to read rotated temporary copy directory {:?}",
+                rotated_dir
+            )
+        })?
+        .filter_map(|entry| entry.ok())
+        .filter_map(|entry| {
+            let path = entry.path();
+            let timestamp = path.file_name()?.to_str()?.parse().ok()?;
+            Some((timestamp, path))
+        })
+        .collect();
+
+    // Newest first, so everything past the limit is the oldest
+    rotated_copies.sort_by(|a, b| b.0.cmp(&a.0));
+    for (_, path) in rotated_copies.iter().skip(keep_temp_copies) {
+        fs::remove_file(path)
+            .with_context(|| format!("While trying to remove old temporary copy {:?}", path))?;
+
+        info!(
+            "Deleted old temporary copy {:?} of file {:?}",
+            path, file.destination
+        );
+    }
+
+    Ok(())
+}
+
 fn copy_all_strategy_cleanup(file: &TrackedFile) -> anyhow::Result<()> {
-    // Path for this tempcopy.
-    let mut tempcopy_path = ROOT_CONFIG
-        .get_config()
-        .apply
-        .apply_metadata_dir
-        .clean_path()?;
+    // Backups in their own location are kept around
+    if file.backup_destination.is_some() {
+        return Ok(());
+    }
 
-    tempcopy_path.push(rename_to_temp_copy(&file.destination));
+    // Recent temporary copies are kept around too
+    if let Some(keep_temp_copies) = ROOT_CONFIG.get_config().apply.keep_temp_copies {
+        return rotate_temp_copies(file, keep_temp_copies);
+    }
+
+    remove_temp_copy(file)
+}
+
+/// Deletes the temporary copy of the file in the temporary directory
+pub fn remove_temp_copy(file: &TrackedFile) -> anyhow::Result<()> {
+    // Path for this tempcopy.
+    let tempcopy_path = get_temp_copy_path(file)?;
     fs::remove_file(&tempcopy_path)
         .with_context(|| "While trying to remove temporary copy of file in temporary directory")?;
 
@@ -94,14 +250,8 @@ fn copy_all_strategy_cleanup(file: &TrackedFile) -> anyhow::Result<()> {
     Ok(())
 }
 
-fn restore_from_temp_copy(file: &TrackedFile) -> anyhow::Result<()> {
-    let mut tempcopy_path = ROOT_CONFIG
-        .get_config()
-        .apply
-        .apply_metadata_dir
-        .clean_path()?;
-
-    tempcopy_path.push(rename_to_temp_copy(&file.destination));
+pub fn restore_from_temp_copy(file: &TrackedFile) -> anyhow::Result<()> {
+    let tempcopy_path = get_temp_copy_path(file)?;
 
     if !tempcopy_path.exists() {
         info!(
@@ -111,8 +261,22 @@ fn restore_from_temp_copy(file: &TrackedFile) -> anyhow::Result<()> {
         return Ok(());
     }
 
+    // Destination may have been removed along with its directory
+    if let Some(destination_dir) = file.destination.parent() {
+        fs::create_dir_all(destination_dir).with_context(|| {
+            format!(
+                "While trying to create directory {:?} to restore file {:?} into",
+                destination_dir, file.destination
+            )
+        })?;
+    }
+
     // Restore the backup
-    fs::copy(&tempcopy_path, &file.destination).with_context(|| {
+    write_destination(&file.destination, |destination_file| {
+        io::copy(&mut File::open(&tempcopy_path)?, destination_file)?;
+        Ok(())
+    })
+    .with_context(|| {
         format!(
             "While trying to restore file {:?} from temporary copy {:?}",
             file.destination, tempcopy_path
@@ -127,6 +291,26 @@ fn restore_from_temp_copy(file: &TrackedFile) -> anyhow::Result<()> {
     Ok(())
 }
 
+/// Restores a single file which is skipped after failing to apply from its
+/// temporary copy, deleting the copy since no rollback will need it anymore.
+pub fn restore_skipped_file(file: &TrackedFile) -> anyhow::Result<()> {
+    if matches!(
+        ROOT_CONFIG.get_config().apply.temp_copy_strategy,
+        TemporaryCopyStrategy::Disabled
+    ) {
+        return Ok(());
+    }
+
+    restore_from_temp_copy(file)?;
+
+    // Backups in their own location are kept around
+    if file.backup_destination.is_none() && get_temp_copy_path(file)?.exists() {
+        remove_temp_copy(file)?;
+    }
+
+    Ok(())
+}
+
 fn restore_all_from_temp_copies(files: &TrackedFileList) -> anyhow::Result<()> {
     let mut restore_errors = Vec::new();
     let mut restore_count = 0;
@@ -134,7 +318,7 @@ fn restore_all_from_temp_copies(files: &TrackedFileList) -> anyhow::Result<()> {
     for file in files.iter() {
         match restore_from_temp_copy(file) {
             Ok(_) => {
-                if get_temp_copy_path(&file.destination)?.exists() {
+                if get_temp_copy_path(file)?.exists() {
                     restore_count += 1;
                 }
             }
@@ -163,36 +347,124 @@ fn restore_all_from_temp_copies(files: &TrackedFileList) -> anyhow::Result<()> {
     Ok(())
 }
 
-fn get_temp_copy_path(destination: &PathBuf) -> anyhow::Result<PathBuf> {
-    let mut tempcopy_path = ROOT_CONFIG
-        .get_config()
-        .apply
-        .apply_metadata_dir
-        .clean_path()?;
+/// Returns the path of the temporary copy of the provided file, its
+/// backup_destination or otherwise in the metadata directory of the file.
+pub fn get_temp_copy_path(file: &TrackedFile) -> anyhow::Result<PathBuf> {
+    if let Some(backup_destination) = &file.backup_destination {
+        return Ok(backup_destination.clone());
+    }
+
+    let mut tempcopy_path = file_metadata_dir(file).clean_path()?;
 
-    tempcopy_path.push(rename_to_temp_copy(destination));
+    tempcopy_path.push(rename_to_temp_copy(&file.destination));
     Ok(tempcopy_path)
 }
 
+/// Returns the file path to the patch of the
+/// last apply in the metadata directory
+pub fn get_patch_file_path(metadata_dir: &PathBuf) -> anyhow::Result<PathBuf> {
+    metadata_dir.join(PATCH_FILE_NAME).clean_path()
+}
+
+/// Writes a unified diff of the changes the apply made to every
+/// file, compared against the temporary copies made before it,
+/// to the metadata directory of each file.
+fn write_apply_patch(files: &TrackedFileList) -> anyhow::Result<()> {
+    let mut patches: HashMap<&PathBuf, String> = HashMap::new();
+
+    for file in files.iter() {
+        let tempcopy_path = get_temp_copy_path(file)?;
+
+        // Files which did not exist before have no temporary copy
+        let old_content = if tempcopy_path.exists() {
+            fs::read_to_string(&tempcopy_path)
+        } else {
+            Ok(String::new())
+        };
+
+        let (Ok(old_content), Ok(new_content)) =
+            (old_content, fs::read_to_string(&file.destination))
+        else {
+            info!(
+                "Skipping {:?} in patch of applied changes since it could not be read as text",
+                file.destination
+            );
+            continue;
+        };
+
+        let destination = file.destination.to_string_lossy();
+        let diff = TextDiff::from_lines(&old_content, &new_content);
+        patches
+            .entry(file_metadata_dir(file))
+            .or_default()
+            .push_str(
+                &diff
+                    .unified_diff()
+                    .header(&destination, &destination)
+                    .to_string(),
+            );
+    }
+
+    for (metadata_dir, patch) in patches {
+        let path = get_patch_file_path(metadata_dir)?;
+        fs::write(&path, patch).with_context(|| {
+            format!("While trying to write patch of applied changes {:?}", path)
+        })?;
+
+        info!("Wrote patch of applied changes to {:?}", path);
+    }
+
+    Ok(())
+}
+
 impl ApplyStrategy for TemporaryCopyStrategy {
+    fn name(self: &Self) -> &'static str {
+        "temp_copy"
+    }
+
+    fn variant(self: &Self) -> String {
+        format!("{:?}", self)
+    }
+
+    fn run_before_apply(self: &Self, _files: &mut TrackedFileList) -> anyhow::Result<()> {
+        // Cleaned up copies are deleted or rotated away, never left to reuse
+        if matches!(self, TemporaryCopyStrategy::CopyRecent { .. })
+            && ROOT_CONFIG.get_config().apply.cleanup_files
+        {
+            typewriter_warn!(
+                "temp_copy_strategy copy_recent never reuses temporary copies with cleanup_files enabled, they are removed after every apply, set cleanup_files=false to reuse them"
+            );
+        }
+
+        Ok(())
+    }
+
     fn run_before_apply_file(self: &Self, file: &mut TrackedFile) -> anyhow::Result<()> {
         match self {
             TemporaryCopyStrategy::CopyAll => copy_all_strategy(file),
+            TemporaryCopyStrategy::CopyRecent { max_age_secs } => {
+                copy_recent_strategy(file, *max_age_secs)
+            }
             TemporaryCopyStrategy::Disabled => Ok(()),
         }
     }
 
     fn run_after_apply(self: &Self, files: &mut TrackedFileList) -> anyhow::Result<()> {
-        if !ROOT_CONFIG.get_config().apply.cleanup_files {
-            return Ok(());
-        }
-
-        // Cleanup all temporary backups after successful apply
         match self {
-            TemporaryCopyStrategy::CopyAll => {
+            TemporaryCopyStrategy::CopyAll | TemporaryCopyStrategy::CopyRecent { .. } => {
+                // Patch needs the temporary copies, so before cleaning them up
+                if let Err(e) = write_apply_patch(files) {
+                    log::warn!("Failed to write patch of applied changes: {:?}", e);
+                }
+
+                if !ROOT_CONFIG.get_config().apply.cleanup_files {
+                    return Ok(());
+                }
+
+                // Cleanup all temporary backups after successful apply
                 for file in files.iter() {
                     if let Err(e) = copy_all_strategy_cleanup(file) {
-                        log::warn!(
+                        typewriter_warn!(
                             "Failed to cleanup temporary backup for {:?}: {:?}",
                             file.destination,
                             e
@@ -207,7 +479,7 @@ impl ApplyStrategy for TemporaryCopyStrategy {
 
     fn run_on_failure(self: &Self, files: &mut TrackedFileList) -> anyhow::Result<()> {
         match self {
-            TemporaryCopyStrategy::CopyAll => {
+            TemporaryCopyStrategy::CopyAll | TemporaryCopyStrategy::CopyRecent { .. } => {
                 log::warn!("Apply operation failed, attempting to restore all files from backup");
                 restore_all_from_temp_copies(files)
             }
@@ -0,0 +1,51 @@
+//! Time spent in each stage of each strategy
+//! during the last apply, for tracking down
+//! slow hooks or hashing after the fact
+
+use std::{
+    collections::HashMap,
+    path::PathBuf,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{apply::store::write_ron, cleanpath::CleanPath, config::ROOT_CONFIG};
+
+/// Name of the stage timings file in the metadata directory
+const TIMINGS_FILE_NAME: &str = ".apply_timings";
+
+/// Stage timings of the last apply stored in the metadata directory
+#[derive(Deserialize, Serialize, Debug, Default)]
+struct ApplyTimings {
+    // Unix timestamp (seconds) of when the apply finished
+    recorded_at: i64,
+
+    // Milliseconds spent in each stage of each strategy,
+    // keyed by "<strategy>.<stage>" e.g "hooks.after_apply"
+    stage_timings: HashMap<String, u64>,
+}
+
+/// Returns the file path to the stage
+/// timings file in the metadata directory
+fn get_timings_file_path() -> anyhow::Result<PathBuf> {
+    ROOT_CONFIG
+        .get_config()
+        .apply
+        .apply_metadata_dir
+        .join(TIMINGS_FILE_NAME)
+        .clean_path()
+}
+
+/// Records the stage timings of an apply, replacing
+/// the ones of the apply before it
+pub fn record_stage_timings(stage_timings: &HashMap<String, u64>) -> anyhow::Result<()> {
+    let path = get_timings_file_path()?;
+
+    let timings = ApplyTimings {
+        recorded_at: SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs() as i64,
+        stage_timings: stage_timings.clone(),
+    };
+
+    write_ron(&path, &timings, "apply stage timings")
+}
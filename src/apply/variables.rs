@@ -2,30 +2,49 @@
 //! typewriter variables in them.
 
 use std::{
+    borrow::Cow,
     collections::HashMap,
-    fs::{self, File, OpenOptions},
-    io::{BufRead, BufReader, Write},
+    fs::{self, File},
+    io::{self, BufRead, BufReader, Write},
+    path::PathBuf,
 };
 
 use anyhow::{Context, bail};
+use encoding_rs::{Encoding, UTF_16BE, UTF_16LE};
+use log::{error, warn};
 use regex::Regex;
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 
 use crate::{
-    apply::strategy::ApplyStrategy,
-    config::ROOT_CONFIG,
+    apply::{
+        atomic::write_destination, preserve::PreservedPermissions, strategy::ApplyStrategy,
+        tempcopy::rename_to_temp_copy,
+    },
+    config::{ROOT_CONFIG, deep_merge},
     file::{TrackedFile, TrackedFileList},
 };
 
 /// Which strategy to use for the variable preprocessing
 /// stage?
-#[derive(Deserialize, Debug, Clone, Copy)]
+#[derive(Deserialize, Serialize, Debug, Clone, Copy)]
 pub enum VariableApplyingStrategy {
     // Enabled, will preprocess and replace variables
     // found in file
     #[serde(rename = "replace_variables")]
     ReplaceVariables,
 
+    // Replace variables found in the destination itself
+    // and write it back, without copying the source over
+    #[serde(rename = "in_place")]
+    InPlace,
+
+    // Render every source file as a Tera template with the
+    // variables as its context, allowing conditionals, loops
+    // and filters rather than only replacing variables
+    #[cfg(feature = "tera-templates")]
+    #[serde(rename = "template_variables")]
+    TemplateVariables,
+
     // Dont preprocess
     #[serde(rename = "disabled")]
     Disabled,
@@ -38,6 +57,10 @@ pub struct VariableApplying {
 
     // Map of variable name -> value for replacing
     var_map: HashMap<String, String>,
+
+    // Directory to also save the rendered content
+    // of every applied file to, if any
+    save_rendered_dir: Option<PathBuf>,
 }
 
 impl Default for VariableApplyingStrategy {
@@ -48,7 +71,17 @@ impl Default for VariableApplyingStrategy {
 
 impl VariableApplying {
     pub fn new(strategy: VariableApplyingStrategy, var_map: HashMap<String, String>) -> Self {
-        Self { strategy, var_map }
+        Self {
+            strategy,
+            var_map,
+            save_rendered_dir: None,
+        }
+    }
+
+    /// Also save the rendered content of every applied
+    /// file to the directory before it is written.
+    pub fn save_rendered_to(self: &mut Self, dir: PathBuf) {
+        self.save_rendered_dir = Some(dir);
     }
 }
 
@@ -64,23 +97,144 @@ fn get_variable_format_regex() -> anyhow::Result<Regex> {
     Ok(Regex::new(&escaped.replace("\\{variable\\}", "([^}]+)"))?)
 }
 
+/// Looks up the encoding set for the provided
+/// file, None if it uses the default of UTF-8.
+fn file_encoding(file: &TrackedFile) -> anyhow::Result<Option<&'static Encoding>> {
+    let Some(label) = &file.encoding else {
+        return Ok(None);
+    };
+
+    match Encoding::for_label(label.as_bytes()) {
+        Some(encoding) => Ok(Some(encoding)),
+        None => bail!(
+            "Unknown encoding {} for file {:?} referenced in configuration file {:?}",
+            label,
+            file.file,
+            file.src
+        ),
+    }
+}
+
+/// Encodes the text of the provided file into its encoding, failing
+/// if the text has characters the encoding cannot represent.
+fn encode_text(
+    text: &str,
+    encoding: &'static Encoding,
+    file: &TrackedFile,
+) -> anyhow::Result<Vec<u8>> {
+    // encoding_rs follows the WHATWG standard and encodes
+    // UTF-16 as UTF-8, so those are encoded by hand.
+    if encoding == UTF_16LE {
+        return Ok(text.encode_utf16().flat_map(u16::to_le_bytes).collect());
+    } else if encoding == UTF_16BE {
+        return Ok(text.encode_utf16().flat_map(u16::to_be_bytes).collect());
+    }
+
+    // Unmappable characters would be silently replaced with HTML entities
+    let (encoded, _, had_errors) = encoding.encode(text);
+    if had_errors {
+        bail!(
+            "File {:?} referenced in configuration file {:?} contains characters which cannot be encoded as {} after its variables are substituted",
+            file.file,
+            file.src,
+            encoding.name()
+        );
+    }
+
+    Ok(encoded.into_owned())
+}
+
+/// Reads the lines at the path of the provided file (its source, or
+/// destination when replacing in place), decoding it from the encoding
+/// of the file first if it has one set.
+fn read_source_lines(
+    file: &TrackedFile,
+    path: &PathBuf,
+    purpose: &str,
+) -> anyhow::Result<Box<dyn Iterator<Item = std::io::Result<String>>>> {
+    let read_context = || {
+        format!(
+            "While trying to read file {:?} referenced in configuration file {:?} {}",
+            path, file.src, purpose
+        )
+    };
+
+    // Plain UTF-8 files are streamed through a buffered reader
+    let Some(encoding) = file_encoding(file)? else {
+        let open_file = File::open(path).with_context(read_context)?;
+        let reader =
+            BufReader::with_capacity(ROOT_CONFIG.get_config().apply.copy_buffer_size, open_file);
+        return Ok(Box::new(reader.lines()));
+    };
+
+    let bytes = fs::read(path).with_context(read_context)?;
+    let (text, _, had_errors) = encoding.decode(&bytes);
+
+    if had_errors {
+        bail!(
+            "File {:?} referenced in configuration file {:?} could not be decoded as {}",
+            path,
+            file.src,
+            encoding.name()
+        );
+    }
+
+    let lines: Vec<std::io::Result<String>> =
+        text.lines().map(|line| Ok(line.to_string())).collect();
+    Ok(Box::new(lines.into_iter()))
+}
+
 impl VariableApplying {
+    /// Path of the file variables are read from, the destination
+    /// when replacing in place and the source otherwise.
+    fn variables_path<'a>(self: &Self, file: &'a TrackedFile) -> &'a PathBuf {
+        match self.strategy {
+            VariableApplyingStrategy::InPlace => &file.destination,
+            _ => &file.file,
+        }
+    }
+
     /// Checks the passed in files content
     /// contains only valid variables in the variable
     /// format supplied, else errors.
     fn check_file_variables_valid(self: &Self, file: &TrackedFile) -> anyhow::Result<()> {
-        // Read in file using a buffered reader (dont exhaust memory on really-large files)
-        let open_file = File::open(&file.file).with_context(|| format!(
-            "While trying to read file {:?} referenced in configuration file {:?} to check for validity of variables",
-            file.file, file.src))?;
+        // Check the file isn't too large to process before reading it
+        let max_source_file_size = ROOT_CONFIG.get_config().apply.max_source_file_size;
+        let path = self.variables_path(file);
+        let file_size = fs::metadata(path)
+            .with_context(|| {
+                format!(
+                    "While trying to get size of file {:?} referenced in configuration file {:?}",
+                    path, file.src
+                )
+            })?
+            .len();
+
+        if file_size > max_source_file_size {
+            bail!(
+                "File {:?} referenced in configuration file {:?} is {} bytes, exceeding the max_source_file_size of {} bytes",
+                path,
+                file.src,
+                file_size,
+                max_source_file_size
+            );
+        }
 
-        let reader = BufReader::new(open_file);
+        // Templates error on undefined variables while rendering
+        #[cfg(feature = "tera-templates")]
+        if let VariableApplyingStrategy::TemplateVariables = self.strategy {
+            self.render_template(file)?;
+            return Ok(());
+        }
+
+        // Read in file using a buffered reader (dont exhaust memory on really-large files)
+        let lines = read_source_lines(file, path, "to check for validity of variables")?;
 
         // Regex for variable matching
         let variable_regex = get_variable_format_regex()?;
 
         // Process line by line
-        for line in reader.lines() {
+        for line in lines {
             let line = line?;
 
             // Find all matches in current line
@@ -88,15 +242,20 @@ impl VariableApplying {
                 // capture[0] is the full match, capture[1] is the variable name
                 let var_name = &capture[1];
 
-                // Check if variable exists in var_map
-                if self.var_map.contains_key(var_name) {
+                // Check if variable exists for this file or is skipped for it
+                if self.file_variable_value(file, var_name).is_some()
+                    || file
+                        .skip_variables
+                        .iter()
+                        .any(|skipped| skipped == var_name)
+                {
                     continue;
                 }
 
                 bail!(
                     "Variable {} found in file {:?} referenced in configuration file {:?} is undefined, aborting operation",
                     var_name,
-                    file.file,
+                    path,
                     file.src
                 );
             }
@@ -105,61 +264,440 @@ impl VariableApplying {
         Ok(())
     }
 
-    /// Replaces all of the variables found in the destination file of the provided file
-    /// with the corresponding values found in the variable map.
-    fn replace_file_variables(self: &Self, file: &TrackedFile) -> anyhow::Result<()> {
-        // Read in file using a buffered reader
-        let open_file = File::open(&file.file).with_context(|| {
+    /// Replaces all of the variables in a single line of the provided file
+    /// with the corresponding values found in the variable map, adding
+    /// the amount of substituted variables to substitution_count.
+    fn replace_line_variables<'a>(
+        self: &Self,
+        variable_regex: &Regex,
+        line: &'a str,
+        file: &TrackedFile,
+        substitution_count: &mut usize,
+    ) -> Cow<'a, str> {
+        variable_regex.replace_all(line, |caps: &regex::Captures| {
+            let var_name = &caps[1];
+
+            // Leave skipped variables in the file as they are
+            if file
+                .skip_variables
+                .iter()
+                .any(|skipped| skipped == var_name)
+            {
+                return caps[0].to_string();
+            }
+
+            // Variables are validated to exist in check_file_variables_valid
+            // before applying, but leave undefined ones as they are otherwise.
+            match self.file_variable_value(file, var_name) {
+                Some(value) => {
+                    *substitution_count += 1;
+                    value.clone()
+                }
+                None => caps[0].to_string(),
+            }
+        })
+    }
+
+    /// Value of the variable substituted into the provided file,
+    /// its own variables take precedence over the global ones.
+    fn file_variable_value<'a>(
+        self: &'a Self,
+        file: &'a TrackedFile,
+        var_name: &str,
+    ) -> Option<&'a String> {
+        file.variables
+            .get(var_name)
+            .or_else(|| self.var_map.get(var_name))
+    }
+
+    /// The resolved variables substituted into files
+    pub fn var_map(self: &Self) -> &HashMap<String, String> {
+        &self.var_map
+    }
+
+    /// Returns the names of the variables that applying the
+    /// provided file would substitute into it, without duplicates.
+    pub fn file_variable_names(self: &Self, file: &TrackedFile) -> anyhow::Result<Vec<String>> {
+        let mut var_names: Vec<String> = Vec::new();
+
+        if let VariableApplyingStrategy::Disabled = self.strategy {
+            return Ok(var_names);
+        }
+
+        let lines = read_source_lines(file, self.variables_path(file), "to find its variables")?;
+
+        // Regex for variable matching
+        let variable_regex = get_variable_format_regex()?;
+
+        for line in lines {
+            let line = line?;
+
+            for capture in variable_regex.captures_iter(&line) {
+                let var_name = &capture[1];
+
+                // Skipped variables are left as they are
+                if file
+                    .skip_variables
+                    .iter()
+                    .any(|skipped| skipped == var_name)
+                    || var_names.iter().any(|existing| existing == var_name)
+                {
+                    continue;
+                }
+
+                var_names.push(var_name.to_string());
+            }
+        }
+
+        Ok(var_names)
+    }
+
+    /// Renders the content that applying the provided file would
+    /// write to its destination in memory, without writing anything.
+    pub fn render_file(self: &Self, file: &TrackedFile) -> anyhow::Result<Vec<u8>> {
+        Ok(self.render_file_counted(file)?.0)
+    }
+
+    /// Renders the provided file in memory, also returning
+    /// the amount of variables substituted into it.
+    fn render_file_counted(self: &Self, file: &TrackedFile) -> anyhow::Result<(Vec<u8>, usize)> {
+        // Copied directly without variables
+        if let VariableApplyingStrategy::Disabled = self.strategy {
+            return Ok((
+                fs::read(&file.file).with_context(|| {
+                    format!(
+                        "While trying to read file {:?} referenced in configuration file {:?} to render it",
+                        file.file, file.src
+                    )
+                })?,
+                0,
+            ));
+        }
+
+        // Substitutions aren't counted for templates
+        #[cfg(feature = "tera-templates")]
+        let (rendered, substitution_count) = match self.strategy {
+            VariableApplyingStrategy::TemplateVariables => (self.render_template(file)?, 0),
+            _ => self.replace_all_variables(file)?,
+        };
+
+        #[cfg(not(feature = "tera-templates"))]
+        let (rendered, substitution_count) = self.replace_all_variables(file)?;
+
+        let rendered = match file_encoding(file)? {
+            Some(encoding) => encode_text(&rendered, encoding, file)?,
+            None => rendered.into_bytes(),
+        };
+
+        Ok((rendered, substitution_count))
+    }
+
+    /// Replaces every variable of the provided file in memory, returning
+    /// the text along with the amount of variables substituted into it.
+    fn replace_all_variables(self: &Self, file: &TrackedFile) -> anyhow::Result<(String, usize)> {
+        let lines = read_source_lines(file, self.variables_path(file), "to render it")?;
+
+        // Regex for variable matching
+        let variable_regex = get_variable_format_regex()?;
+
+        // Process line by line, same as replace_file_variables
+        let mut rendered = String::new();
+        let mut substitution_count = 0;
+        for line in lines {
+            let line = line?;
+            rendered.push_str(&self.replace_line_variables(
+                &variable_regex,
+                &line,
+                file,
+                &mut substitution_count,
+            ));
+            rendered.push('\n');
+        }
+
+        Ok((rendered, substitution_count))
+    }
+
+    /// Renders the source of the provided file as a Tera template with
+    /// the variables of the file as its context, erroring on undefined ones.
+    #[cfg(feature = "tera-templates")]
+    fn render_template(self: &Self, file: &TrackedFile) -> anyhow::Result<String> {
+        let lines = read_source_lines(file, &file.file, "to render it as a template")?;
+        // Lines are ended the same way replacing variables does
+        let mut template = String::new();
+        for line in lines {
+            template.push_str(&line?);
+            template.push('\n');
+        }
+
+        // Own variables of the file take precedence over the global ones
+        let mut context = tera::Context::new();
+        for (name, value) in self.var_map.iter().chain(file.variables.iter()) {
+            context.insert(name.as_str(), value);
+        }
+
+        tera::Tera::one_off(&template, &context, false).with_context(|| {
             format!(
-                "While trying to read file {:?} referenced in configuration file {:?} to replace variables",
+                "While trying to render file {:?} referenced in configuration file {:?} as a template",
                 file.file, file.src
             )
-        })?;
+        })
+    }
+
+    /// Saves the rendered content of the file to the directory,
+    /// named after its destination like temporary copies are.
+    fn save_rendered_file(self: &Self, file: &TrackedFile, dir: &PathBuf) -> anyhow::Result<()> {
+        let rendered = self.render_file(file)?;
+        let rendered_path = dir.join(rename_to_temp_copy(&file.destination));
+
+        fs::create_dir_all(dir)
+            .with_context(|| format!("While trying to make rendered directory {:?}", dir))?;
+
+        fs::write(&rendered_path, rendered).with_context(|| {
+            format!(
+                "While trying to save rendered file {:?} referenced in configuration file {:?} to {:?}",
+                file.file, file.src, rendered_path
+            )
+        })
+    }
+
+    /// Deep merges the rendered source over the current destination,
+    /// both parsed as TOML, and writes the merged result to the destination.
+    ///
+    /// Returns the amount of variables that were substituted.
+    fn merge_file_variables(self: &Self, file: &TrackedFile) -> anyhow::Result<usize> {
+        let (rendered, substitution_count) = self.render_file_counted(file)?;
 
-        // Open destination for writing to
-        let mut destination_file = OpenOptions::new()
-            .write(true)
-            .truncate(true)
-            .open(&file.destination)
+        let source: toml::Table = toml::from_str(&String::from_utf8(rendered)?)
             .with_context(|| {
                 format!(
-                    "While trying to write to file {:?} referenced in configuration file {:?} to replace variables",
-                    file.destination, file.src
+                    "While trying to parse file {:?} referenced in configuration file {:?} as TOML to merge it",
+                    file.file, file.src
                 )
             })?;
 
-        let reader = BufReader::new(open_file);
+        let destination_content = fs::read_to_string(&file.destination).with_context(|| {
+            format!(
+                "While trying to read file {:?} referenced in configuration file {:?} to merge into it",
+                file.destination, file.src
+            )
+        })?;
 
+        let mut merged: toml::Table = toml::from_str(&destination_content).with_context(|| {
+            format!(
+                "While trying to parse file {:?} referenced in configuration file {:?} as TOML to merge into it",
+                file.destination, file.src
+            )
+        })?;
+
+        // Source wins on conflicting keys
+        deep_merge(&mut merged, &source);
+
+        let merged = toml::to_string_pretty(&merged)?;
+        write_destination(&file.destination, |destination_file| {
+            Ok(destination_file.write_all(merged.as_bytes())?)
+        })
+        .with_context(|| {
+            format!(
+                "While trying to write merged file {:?} referenced in configuration file {:?}",
+                file.destination, file.src
+            )
+        })?;
+
+        Ok(substitution_count)
+    }
+
+    /// Replaces all of the variables found in the destination file of the provided file
+    /// with the corresponding values found in the variable map.
+    ///
+    /// Returns the amount of variables that were substituted.
+    fn replace_file_variables(self: &Self, file: &TrackedFile) -> anyhow::Result<usize> {
+        // Read in file using a buffered reader
+        let lines = read_source_lines(file, &file.file, "to replace variables")?;
+        let encoding = file_encoding(file)?;
+
+        write_destination(&file.destination, |destination_file| {
+            self.write_replaced_lines(lines, encoding, file, destination_file)
+        })
+        .with_context(|| {
+            format!(
+                "While trying to write to file {:?} referenced in configuration file {:?} to replace variables",
+                file.destination, file.src
+            )
+        })
+    }
+
+    /// Writes the lines of the provided file to the destination file with
+    /// all of their variables replaced, encoding them if it has an encoding.
+    ///
+    /// Returns the amount of variables that were substituted.
+    fn write_replaced_lines(
+        self: &Self,
+        lines: Box<dyn Iterator<Item = std::io::Result<String>>>,
+        encoding: Option<&'static Encoding>,
+        file: &TrackedFile,
+        destination_file: &mut File,
+    ) -> anyhow::Result<usize> {
         // Regex for variable matching
         let variable_regex = get_variable_format_regex()?;
 
+        // Amount of variables replaced in this file
+        let mut substitution_count = 0;
+
+        // Encoded files have to be re-encoded as a whole
+        let mut encoded_content = String::new();
+
         // Process line by line
-        for line in reader.lines() {
+        for line in lines {
             let line = line?;
 
             // Replace all variables in this line
-            let replaced_line = variable_regex.replace_all(&line, |caps: &regex::Captures| {
-                let var_name = &caps[1];
-                // We already validated all variables exist in check_file_variables_valid
-                // so we can safely unwrap here unless some TOCTOU thing happened
-                self.var_map.get(var_name).unwrap().as_str()
-            });
+            let replaced_line =
+                self.replace_line_variables(&variable_regex, &line, file, &mut substitution_count);
 
             // Write the replaced line to temp file
-            writeln!(destination_file, "{}", replaced_line)?;
+            if encoding.is_some() {
+                encoded_content.push_str(&replaced_line);
+                encoded_content.push('\n');
+            } else {
+                writeln!(destination_file, "{}", replaced_line)?;
+            }
+        }
+
+        if let Some(encoding) = encoding {
+            destination_file.write_all(&encode_text(&encoded_content, encoding, file)?)?;
+        }
+
+        Ok(substitution_count)
+    }
+
+    /// Re-reads the destination of the provided file after its variables
+    /// were replaced and logs any variable patterns still left in it, which
+    /// aren't skipped for the file. Errors are logged instead of warnings
+    /// if every variable is meant to be validated to be defined.
+    fn validate_no_remaining_patterns(self: &Self, file: &TrackedFile) -> anyhow::Result<()> {
+        let lines =
+            read_source_lines(file, &file.destination, "to check for unresolved variables")?;
+
+        // Regex for variable matching
+        let variable_regex = get_variable_format_regex()?;
+        let validate_variables = ROOT_CONFIG.get_config().variables.validate_variables;
+
+        for (line_number, line) in lines.enumerate() {
+            let line = line?;
+
+            for capture in variable_regex.captures_iter(&line) {
+                let var_name = &capture[1];
+
+                if file
+                    .skip_variables
+                    .iter()
+                    .any(|skipped| skipped == var_name)
+                {
+                    continue;
+                }
+
+                if validate_variables {
+                    error!(
+                        "Unresolved variable {} left on line {} of {:?} referenced in configuration file {:?}",
+                        var_name,
+                        line_number + 1,
+                        file.destination,
+                        file.src
+                    );
+                } else {
+                    warn!(
+                        "Unresolved variable {} left on line {} of {:?} referenced in configuration file {:?}",
+                        var_name,
+                        line_number + 1,
+                        file.destination,
+                        file.src
+                    );
+                }
+            }
         }
 
         Ok(())
     }
+
+    /// Writes the provided file to its destination
+    /// according to the variable applying strategy.
+    fn write_file(self: &Self, file: &mut TrackedFile) -> anyhow::Result<()> {
+        // Merged into the destination rather than replacing it
+        if file.merge {
+            file.substitution_count = self.merge_file_variables(file)?;
+            return Ok(());
+        }
+
+        match self.strategy {
+            VariableApplyingStrategy::Disabled => {
+                // Copy file to destination directly, no variabling
+                write_destination(&file.destination, |destination_file| {
+                    io::copy(&mut File::open(&file.file)?, destination_file)?;
+                    Ok(())
+                })
+                .with_context(|| {
+                    format!(
+                        "While trying to apply {:?} to {:?} referenced by config {:?}",
+                        file.file, file.destination, file.src
+                    )
+                })
+            }
+            VariableApplyingStrategy::InPlace => {
+                // Rendered fully before writing, since it reads the destination
+                let (rendered, substitution_count) = self.render_file_counted(file)?;
+                write_destination(&file.destination, |destination_file| {
+                    Ok(destination_file.write_all(&rendered)?)
+                })
+                .with_context(|| {
+                    format!(
+                        "While trying to replace variables in place in {:?} referenced by config {:?}",
+                        file.destination, file.src
+                    )
+                })?;
+
+                file.substitution_count = substitution_count;
+                Ok(())
+            }
+            VariableApplyingStrategy::ReplaceVariables => {
+                file.substitution_count = self.replace_file_variables(file)?;
+                self.validate_no_remaining_patterns(file)
+            }
+            #[cfg(feature = "tera-templates")]
+            VariableApplyingStrategy::TemplateVariables => {
+                let rendered = self.render_file(file)?;
+                write_destination(&file.destination, |destination_file| {
+                    Ok(destination_file.write_all(&rendered)?)
+                })
+                .with_context(|| {
+                    format!(
+                        "While trying to write rendered template {:?} to {:?} referenced by config {:?}",
+                        file.file, file.destination, file.src
+                    )
+                })
+            }
+        }
+    }
 }
 
 impl ApplyStrategy for VariableApplying {
+    fn name(self: &Self) -> &'static str {
+        "variables"
+    }
+
+    fn variant(self: &Self) -> String {
+        format!("{:?}", self.strategy)
+    }
+
     fn run_before_apply(self: &Self, files: &mut TrackedFileList) -> anyhow::Result<()> {
         match self.strategy {
             VariableApplyingStrategy::Disabled => return Ok(()),
             _ => {}
         }
 
+        if !ROOT_CONFIG.get_config().variables.validate_variables {
+            return Ok(());
+        }
+
         // Try validate all variables exist before running
         for file in files.iter() {
             self.check_file_variables_valid(file)?;
@@ -169,19 +707,86 @@ impl ApplyStrategy for VariableApplying {
     }
 
     fn run_after_apply_file(self: &Self, file: &mut TrackedFile) -> anyhow::Result<()> {
-        match self.strategy {
-            VariableApplyingStrategy::Disabled => {
-                // Copy file to destination directly, no variabling
-                fs::copy(&file.file, &file.destination).with_context(|| {
-                    format!(
-                        "While trying to apply {:?} to {:?} referenced by config {:?}",
-                        file.file, file.destination, file.src
-                    )
-                })?;
-
-                Ok(())
-            }
-            _ => self.replace_file_variables(file),
+        // Snapshot of what is about to be written
+        if let Some(dir) = &self.save_rendered_dir {
+            self.save_rendered_file(file, dir)?;
         }
+
+        // Read before writing, which may replace the destination
+        let permissions = PreservedPermissions::capture(file)?;
+        self.write_file(file)?;
+        permissions.restore(file)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+
+    /// Tracked file reading from the source, with its own variables
+    fn tracked_file(source: PathBuf, variables: &str) -> TrackedFile {
+        let mut file: TrackedFile = toml::from_str(&format!(
+            "file = \"source\"\ndestination = \"destination\"\nvariables = {{ {} }}",
+            variables
+        ))
+        .unwrap();
+        file.file = source;
+        file
+    }
+
+    /// Variable applying with a single global variable
+    fn variable_applying() -> VariableApplying {
+        ROOT_CONFIG.set_config(Config::default());
+
+        VariableApplying::new(
+            VariableApplyingStrategy::ReplaceVariables,
+            HashMap::from([("theme".to_string(), "light".to_string())]),
+        )
+    }
+
+    #[test]
+    fn file_variable_shadows_global_variable() {
+        let variables = variable_applying();
+        let overriding = tracked_file(PathBuf::from("overriding"), "theme = \"dark\"");
+        let global = tracked_file(PathBuf::from("global"), "");
+
+        assert_eq!(
+            variables.file_variable_value(&overriding, "theme"),
+            Some(&"dark".to_string())
+        );
+        assert_eq!(
+            variables.file_variable_value(&global, "theme"),
+            Some(&"light".to_string())
+        );
+    }
+
+    #[test]
+    fn other_files_keep_global_value_when_rendered() {
+        let variables = variable_applying();
+
+        let dir = tempfile::tempdir().unwrap();
+        let source = dir.path().join("source");
+        fs::write(&source, "theme=$TYPEWRITER{theme}\n").unwrap();
+
+        // Both files are from the same apply, only one overrides the variable
+        let overriding = tracked_file(source.clone(), "theme = \"dark\"");
+        let global = tracked_file(source, "");
+
+        assert_eq!(variables.render_file(&overriding).unwrap(), b"theme=dark\n");
+        assert_eq!(variables.render_file(&global).unwrap(), b"theme=light\n");
+    }
+
+    #[test]
+    fn file_only_variables_are_defined_for_their_file() {
+        let variables = variable_applying();
+        let file = tracked_file(PathBuf::from("source"), "font_size = \"14\"");
+        let other = tracked_file(PathBuf::from("source"), "");
+
+        assert_eq!(
+            variables.file_variable_value(&file, "font_size"),
+            Some(&"14".to_string())
+        );
+        assert_eq!(variables.file_variable_value(&other, "font_size"), None);
     }
 }
@@ -1,8 +1,8 @@
 //! Argument Parsing for typewriter using Clap
 
-use std::fmt::Display;
+use std::{fmt::Display, path::PathBuf};
 
-use clap::{Parser, Subcommand};
+use clap::{Parser, Subcommand, ValueEnum};
 
 // Root-arguments for typewriter
 #[derive(Parser)]
@@ -11,6 +11,28 @@ pub struct Args {
     /// Which operation to run with typewriter
     #[command(subcommand)]
     pub command: Commands,
+
+    /// When to colour output, auto only colours
+    /// terminals and respects NO_COLOR
+    #[arg(long, global = true, value_enum, default_value_t = ColorMode::Auto)]
+    pub color: ColorMode,
+
+    /// Disable coloured output, same as --color never
+    #[arg(long, global = true, conflicts_with = "color")]
+    pub no_color: bool,
+}
+
+// When output should be coloured
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ColorMode {
+    // Always colour output
+    Always,
+
+    // Colour output if it is a terminal and NO_COLOR isn't set
+    Auto,
+
+    // Never colour output
+    Never,
 }
 
 // Enum for commands for different operations within typewriter
@@ -24,25 +46,345 @@ pub enum Commands {
     },
 
     /// Applies the supplied typewriter configuration file to the system
-    Apply {
+    Apply(ApplyArgs),
+
+    /// Restores destination files from the temporary copies
+    /// left behind by a previous failed apply
+    Rollback {
+        /// Name of the configuration file
+        #[arg(short, long)]
+        file: String,
+
+        /// Name of the provided section for
+        /// Quill TOML extensions.
+        #[arg(short, long, default_value = "typewriter")]
+        section: String,
+    },
+
+    /// Shows the patch of the changes made to files
+    /// by the last successful apply
+    ShowPatch {
+        /// Name of the configuration file
+        #[arg(short, long)]
+        file: String,
+
+        /// Name of the provided section for
+        /// Quill TOML extensions.
+        #[arg(short, long, default_value = "typewriter")]
+        section: String,
+    },
+
+    /// Recomputes the stored checksums with another
+    /// checkdiff strategy, for switching between them
+    MigrateCheckdiff {
+        /// Name of the configuration file
+        #[arg(short, long)]
+        file: String,
+
+        /// Checkdiff strategy the checksums are stored with
+        #[arg(long)]
+        from: String,
+
+        /// Checkdiff strategy to store the checksums with
+        #[arg(long)]
+        to: String,
+
+        /// Name of the provided section for
+        /// Quill TOML extensions.
+        #[arg(short, long, default_value = "typewriter")]
+        section: String,
+    },
+
+    /// Shows which tracked files are out of
+    /// sync with their destinations
+    Status {
+        /// Name of the configuration file
+        #[arg(short, long)]
+        file: String,
+
+        /// Name of the provided section for
+        /// Quill TOML extensions.
+        #[arg(short, long, default_value = "typewriter")]
+        section: String,
+    },
+
+    /// Shows the diff between the destinations and what
+    /// applying the tracked files would write to them
+    Diff {
+        /// Name of the configuration file
+        #[arg(short, long)]
+        file: String,
+
+        /// Name of the provided section for
+        /// Quill TOML extensions.
+        #[arg(short, long, default_value = "typewriter")]
+        section: String,
+    },
+
+    /// Checks the configuration is valid without
+    /// applying it or running any commands
+    Validate {
+        /// Name of the configuration file
+        #[arg(short, long)]
+        file: String,
+
+        /// Name of the provided section for
+        /// Quill TOML extensions.
+        #[arg(short, long, default_value = "typewriter")]
+        section: String,
+    },
+
+    /// Lists every tracked file with its destination,
+    /// group and whether they exist
+    List {
+        /// Name of the configuration file
+        #[arg(short, long)]
+        file: String,
+
+        /// Name of the provided section for
+        /// Quill TOML extensions.
+        #[arg(short, long, default_value = "typewriter")]
+        section: String,
+
+        /// Print the files as a JSON array for scripting
+        #[arg(long)]
+        json: bool,
+    },
+
+    /// Shows the resolved values of the variables, or
+    /// how a single variable was resolved
+    Inspect {
+        /// Name of the configuration file
+        #[arg(short, long)]
+        file: String,
+
+        /// Name of the provided section for
+        /// Quill TOML extensions.
+        #[arg(short, long, default_value = "typewriter")]
+        section: String,
+
+        /// Name of the variable to show the details of
+        name: Option<String>,
+
+        /// Show the values of sensitive and prompt variables
+        #[arg(long)]
+        reveal: bool,
+    },
+
+    /// Deletes the temporary copies and checksums
+    /// left in the metadata directories
+    Clean {
         /// Name of the configuration file
         #[arg(short, long)]
         file: String,
 
         /// Name of the provided section for
-        /// Quill TOML extensions. ALL of the config files
-        /// should share this section to minimise confusion.
+        /// Quill TOML extensions.
         #[arg(short, long, default_value = "typewriter")]
         section: String,
+
+        /// Only delete temporary copies, keeping the checksums
+        #[arg(long, conflicts_with = "only_checkdiff")]
+        only_backups: bool,
+
+        /// Only delete the checksums, keeping the temporary copies
+        #[arg(long)]
+        only_checkdiff: bool,
     },
 }
 
+// Arguments for the apply command
+#[derive(clap::Args, Debug)]
+pub struct ApplyArgs {
+    /// Name of the configuration file
+    #[arg(short, long)]
+    pub file: String,
+
+    /// Name of the provided section for
+    /// Quill TOML extensions. ALL of the config files
+    /// should share this section to minimise confusion.
+    #[arg(short, long, default_value = "typewriter")]
+    pub section: String,
+
+    /// Skip all global and per-file hooks for this apply
+    /// regardless of the configuration
+    #[arg(
+        long,
+        env = "TYPEWRITER_NO_HOOKS",
+        value_parser = clap::builder::BoolishValueParser::new()
+    )]
+    pub no_hooks: bool,
+
+    /// Skip checking whether destination files were changed
+    /// outside of typewriter since the last apply
+    #[arg(
+        long,
+        env = "TYPEWRITER_NO_CHECKDIFF",
+        value_parser = clap::builder::BoolishValueParser::new()
+    )]
+    pub no_checkdiff: bool,
+
+    /// Skip making temporary backups of destination files,
+    /// rollback will not be possible if the apply fails
+    #[arg(long)]
+    pub no_backup: bool,
+
+    /// Only run the global pre_apply and post_apply hooks
+    /// without applying any files
+    #[arg(long, conflicts_with = "no_hooks")]
+    pub only_hooks: bool,
+
+    /// Abort the apply if it would touch more files than this
+    #[arg(long)]
+    pub max_files: Option<usize>,
+
+    /// Resume a previously failed apply, skipping the
+    /// files which it had already completed
+    #[arg(long)]
+    pub resume: bool,
+
+    /// Skip files whose rendered content is unchanged since
+    /// the checksum stored by the last apply
+    #[arg(long)]
+    pub skip_unchanged: bool,
+
+    /// Write a JSON report of what the apply would do to
+    /// this path as well, only alongside --dry-run
+    #[arg(long, requires = "dry_run")]
+    pub dry_run_report: Option<PathBuf>,
+
+    /// Name of the profile in the root configuration
+    /// file to merge over the config for this apply
+    #[arg(long)]
+    pub profile: Option<String>,
+
+    /// Verify the checksums of the applied files against
+    /// the stored checksums once the apply is done
+    #[arg(long)]
+    pub verify_after: bool,
+
+    /// Print the ordered list of strategies the apply
+    /// would use and exit without applying anything
+    #[arg(long)]
+    pub list_strategies: bool,
+
+    /// Only parse and validate the configuration files,
+    /// exiting without resolving variables or applying
+    #[arg(long)]
+    pub config_check_only: bool,
+
+    /// Confirm the apply and every shell command
+    /// regardless of the configuration
+    #[arg(long)]
+    pub interactive: bool,
+
+    /// Only apply files which haven't been applied since this
+    /// RFC 3339 datetime, e.g 2024-01-01T00:00:00Z
+    #[arg(long)]
+    pub since: Option<String>,
+
+    /// Skip checking every variable referenced in
+    /// files is defined, leaving undefined ones as is
+    #[arg(long)]
+    pub no_variable_validation: bool,
+
+    /// Also save the rendered content of every applied
+    /// file to this directory before it is written
+    #[arg(long)]
+    pub save_rendered: Option<PathBuf>,
+
+    /// Only apply files with these tags
+    #[arg(long, value_delimiter = ',')]
+    pub tags: Vec<String>,
+
+    /// Whether files need any or all of the tags in --tags
+    #[arg(long, value_enum, default_value_t = TagFilterMode::Any)]
+    pub tag_filter_mode: TagFilterMode,
+
+    /// Maximum size in bytes of the output
+    /// of each hook to keep in memory
+    #[arg(long)]
+    pub max_hook_output_size: Option<usize>,
+
+    /// Set an environment variable (KEY=VALUE) for this
+    /// apply before the config is parsed, can be repeated
+    #[arg(long, value_name = "KEY=VALUE")]
+    pub env: Vec<String>,
+
+    /// Print the resolved value of every variable and exit
+    /// without applying anything or running any hooks
+    #[arg(long)]
+    pub dry_run_vars: bool,
+
+    /// Print a summary of the configuration and confirm
+    /// continuing with it before applying
+    #[arg(long)]
+    pub config_summary: bool,
+
+    /// Compare the configuration with another configuration
+    /// file and exit, erroring if they are different
+    #[arg(long, value_name = "OTHER_FILE")]
+    pub config_diff: Option<String>,
+
+    /// Write every destination under this directory instead
+    /// of its real location, for testing an apply safely
+    #[arg(long)]
+    pub output_dir: Option<PathBuf>,
+
+    /// Print which files the apply would change and
+    /// exit without writing anything
+    #[arg(
+        long,
+        env = "TYPEWRITER_DRY_RUN",
+        value_parser = clap::builder::BoolishValueParser::new()
+    )]
+    pub dry_run: bool,
+
+    /// Only apply files in this group, along
+    /// with files which aren't in any group
+    #[arg(short = 'g', long)]
+    pub only_group: Option<String>,
+
+    /// Skip applying files in these groups
+    #[arg(long, value_delimiter = ',')]
+    pub exclude_group: Vec<String>,
+
+    /// Apply without confirming, overwriting destinations
+    /// changed outside of typewriter since the last apply
+    #[arg(
+        long,
+        conflicts_with = "interactive",
+        env = "TYPEWRITER_FORCE",
+        value_parser = clap::builder::BoolishValueParser::new()
+    )]
+    pub force: bool,
+}
+
+// How files are matched against the tags of apply --tags
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TagFilterMode {
+    // Files with at least one of the tags
+    Any,
+
+    // Files with every one of the tags
+    All,
+}
+
 impl Display for Commands {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         // Should display what type of command.
         match self {
             Commands::Init { .. } => write!(f, "init"),
-            Commands::Apply { .. } => write!(f, "apply"),
+            Commands::Apply(..) => write!(f, "apply"),
+            Commands::Rollback { .. } => write!(f, "rollback"),
+            Commands::ShowPatch { .. } => write!(f, "show-patch"),
+            Commands::MigrateCheckdiff { .. } => write!(f, "migrate-checkdiff"),
+            Commands::Status { .. } => write!(f, "status"),
+            Commands::Diff { .. } => write!(f, "diff"),
+            Commands::Validate { .. } => write!(f, "validate"),
+            Commands::List { .. } => write!(f, "list"),
+            Commands::Inspect { .. } => write!(f, "inspect"),
+            Commands::Clean { .. } => write!(f, "clean"),
         }
     }
 }
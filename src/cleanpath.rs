@@ -1,6 +1,6 @@
 use anyhow::{Context, Result};
 use path_absolutize::Absolutize;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 
 /// Cleanup paths fully within the system of typewriter
 /// should handle ., .., ~, etc.
@@ -38,3 +38,21 @@ impl CleanPath for PathBuf {
         Ok(absolute.into_owned())
     }
 }
+
+impl CleanPath for Path {
+    fn clean_path(&self) -> Result<PathBuf> {
+        self.to_path_buf().clean_path()
+    }
+}
+
+impl CleanPath for str {
+    fn clean_path(&self) -> Result<PathBuf> {
+        PathBuf::from(self).clean_path()
+    }
+}
+
+impl CleanPath for String {
+    fn clean_path(&self) -> Result<PathBuf> {
+        self.as_str().clean_path()
+    }
+}
@@ -1,27 +1,28 @@
 //! Centralized command execution for typewriter
 use anyhow::{Context, Result, bail};
 use inquire::Confirm;
-use log::info;
-use serde::Deserialize;
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
 use std::{
-    io::{BufRead, BufReader},
-    path::PathBuf,
+    collections::HashMap,
+    io::{self, Read, Write},
+    path::{Path, PathBuf},
     process::{Command, Stdio},
     thread,
 };
 
 use crate::config::ROOT_CONFIG;
 
-#[derive(Deserialize, Debug)]
+#[derive(Deserialize, Serialize, Debug)]
 pub struct CommandConfig {
     // Shell to run commands in
     #[serde(default = "default_shell")]
     pub shell: String,
 
-    // Argument to provide to the shell to be capable
-    // of running the commands
-    #[serde(default = "default_shell_command_arg")]
-    pub shell_command_arg: String,
+    // Argument to provide to the shell to be capable of running
+    // the commands, picked based on the shell if not set
+    #[serde(default)]
+    pub shell_command_arg: Option<String>,
 
     // Confirm on running any shell commands in the
     // config
@@ -39,17 +40,33 @@ pub struct CommandConfig {
     // Inherit stderr to allow printing to stderr from commands?
     #[serde(default = "default_is_true")]
     pub commands_inherit_stderr: bool,
+    // Environment variables set for every command,
+    // before the ones specific to the command
+    #[serde(default)]
+    pub env: HashMap<String, String>,
 }
 
 impl Default for CommandConfig {
     fn default() -> Self {
         Self {
             shell: default_shell(),
-            shell_command_arg: default_shell_command_arg(),
+            shell_command_arg: None,
             confirm_shell_commands: default_is_true(),
             commands_inherit_stdin: default_is_true(),
             commands_inherit_stdout: default_is_true(),
             commands_inherit_stderr: default_is_true(),
+            env: HashMap::new(),
+        }
+    }
+}
+
+impl CommandConfig {
+    /// Argument given to the shell to run a command, the configured one
+    /// or otherwise the one the configured shell expects
+    pub fn shell_command_arg(self: &Self) -> &str {
+        match &self.shell_command_arg {
+            Some(shell_command_arg) => shell_command_arg,
+            None => default_shell_command_arg(&self.shell),
         }
     }
 }
@@ -75,20 +92,29 @@ pub fn execute_command(command: &str, context: &CommandContext) -> Result<String
 
     // Build command
     let mut cmd = Command::new(&command_config.shell);
-    cmd.arg(&command_config.shell_command_arg).arg(command);
+    cmd.arg(command_config.shell_command_arg()).arg(command);
 
     // Set working directory if specified
     if let Some(workdir) = &context.workdir {
         cmd.current_dir(workdir);
     }
 
+    // Set global environment variables first so the
+    // ones specific to this command can override them
+    for (key, value) in &command_config.env {
+        cmd.env(key, value);
+    }
+
     // Set environment variables
     for (key, value) in &context.env_vars {
         cmd.env(key, value);
     }
 
-    // Inheriting stdin so commands can get user input until completion.
-    if command_config.commands_inherit_stdin {
+    // Piping in data takes priority over inheriting stdin, otherwise
+    // inheriting stdin so commands can get user input until completion.
+    if context.stdin_data.is_some() {
+        cmd.stdin(Stdio::piped());
+    } else if command_config.commands_inherit_stdin {
         cmd.stdin(Stdio::inherit());
     }
 
@@ -100,6 +126,19 @@ pub fn execute_command(command: &str, context: &CommandContext) -> Result<String
         .spawn()
         .with_context(|| format!("While spawning command: {}", command))?;
 
+    // Write data to stdin in a separate thread so a command
+    // filling up its stdout before reading everything can't deadlock
+    let stdin_handle = match (&context.stdin_data, child.stdin.take()) {
+        (Some(stdin_data), Some(mut stdin)) => {
+            let stdin_data = stdin_data.clone();
+            Some(thread::spawn(move || {
+                // Dropping stdin afterwards closes it for the command
+                let _ = stdin.write_all(stdin_data.as_bytes());
+            }))
+        }
+        _ => None,
+    };
+
     // Capture and print stdout in a separate thread
     let stdout = child
         .stdout
@@ -110,19 +149,14 @@ pub fn execute_command(command: &str, context: &CommandContext) -> Result<String
     let display_stdout = command_config.commands_inherit_stdout;
 
     // Read from stdout into both the reader and to the actual stdout.
-    let stdout_reader = BufReader::new(stdout);
+    let max_output_size = context.max_output_size;
     let stdout_handle = thread::spawn(move || {
-        let mut output = String::new();
-        for line in stdout_reader.lines() {
-            if let Ok(line) = line {
-                if display_stdout {
-                    println!("{}", line);
-                }
-                output.push_str(&line);
-                output.push('\n');
-            }
-        }
-        output
+        capture_stream(
+            stdout,
+            display_stdout.then(io::stdout),
+            max_output_size,
+            "stdout",
+        )
     });
 
     // Capture and print stderr in a separate thread
@@ -135,20 +169,13 @@ pub fn execute_command(command: &str, context: &CommandContext) -> Result<String
     let display_stderr = command_config.commands_inherit_stderr;
 
     // Read from stderr into both the reader and to the actual stderr.
-    let stderr_reader = BufReader::new(stderr);
     let stderr_handle = thread::spawn(move || {
-        let mut output = String::new();
-        for line in stderr_reader.lines() {
-            if let Ok(line) = line {
-                if display_stderr {
-                    eprintln!("{}", line);
-                }
-
-                output.push_str(&line);
-                output.push('\n');
-            }
-        }
-        output
+        capture_stream(
+            stderr,
+            display_stderr.then(io::stderr),
+            max_output_size,
+            "stderr",
+        )
     });
 
     // Wait for the process to complete
@@ -157,6 +184,9 @@ pub fn execute_command(command: &str, context: &CommandContext) -> Result<String
         .with_context(|| format!("While waiting for command: {}", command))?;
 
     // Collect output from threads
+    if let Some(stdin_handle) = stdin_handle {
+        let _ = stdin_handle.join();
+    }
     let stdout_output = stdout_handle.join().unwrap_or_default();
     let stderr_output = stderr_handle.join().unwrap_or_default();
 
@@ -177,6 +207,8 @@ pub struct CommandContext {
     pub workdir: Option<PathBuf>,
     pub env_vars: Vec<(String, String)>,
     pub description: Option<String>,
+    pub stdin_data: Option<String>,
+    pub max_output_size: Option<usize>,
 }
 
 impl Default for CommandContext {
@@ -185,17 +217,88 @@ impl Default for CommandContext {
             workdir: None,
             env_vars: Vec::new(),
             description: None,
+            stdin_data: None,
+            max_output_size: None,
         }
     }
 }
 
-/// Defaults for configuration options
-fn default_shell_command_arg() -> String {
-    String::from("-c")
+/// Size of the chunks the output of commands is read in
+const CAPTURE_CHUNK_SIZE: usize = 8192;
+
+/// Reads the output stream of a command until it is closed, displaying it
+/// as it comes in if wanted, and captures it up to the maximum size.
+///
+/// The stream is read in fixed-size chunks so a command printing a huge
+/// line never has more than a chunk of it in memory past the maximum,
+/// everything after the maximum is still read so the command isn't blocked.
+fn capture_stream(
+    mut stream: impl Read,
+    mut display: Option<impl Write>,
+    max_output_size: Option<usize>,
+    stream_name: &str,
+) -> String {
+    let mut output = Vec::new();
+    let mut chunk = [0u8; CAPTURE_CHUNK_SIZE];
+    let mut truncated = false;
+
+    loop {
+        let read = match stream.read(&mut chunk) {
+            Ok(0) => break,
+            Ok(read) => read,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            Err(_) => break,
+        };
+
+        if let Some(display) = &mut display {
+            let _ = display.write_all(&chunk[..read]);
+        }
+
+        // Only as much as is left of the maximum is kept
+        let remaining = max_output_size.map_or(read, |max_output_size| {
+            max_output_size.saturating_sub(output.len())
+        });
+        output.extend_from_slice(&chunk[..read.min(remaining)]);
+
+        if read > remaining && !truncated {
+            truncated = true;
+            warn!(
+                "Captured {} of command reached the maximum output size of {} bytes, the rest is discarded",
+                stream_name,
+                max_output_size.unwrap_or_default()
+            );
+        }
+    }
+
+    String::from_utf8_lossy(&output).into_owned()
+}
+
+/// Argument the shell expects to be given a command with
+fn default_shell_command_arg(shell: &str) -> &'static str {
+    let shell_name = Path::new(shell)
+        .file_stem()
+        .map(|name| name.to_string_lossy().to_lowercase())
+        .unwrap_or_default();
+
+    match shell_name.as_str() {
+        "powershell" | "pwsh" => "-Command",
+        "cmd" => "/C",
+        _ => "-c",
+    }
+}
+
+/// Defaults for configuration options, the shell of the user, from $SHELL on unix
+#[cfg(not(windows))]
+fn default_shell() -> String {
+    std::env::var("SHELL")
+        .ok()
+        .filter(|shell| !shell.is_empty())
+        .unwrap_or_else(|| String::from("/bin/sh"))
 }
 
+#[cfg(windows)]
 fn default_shell() -> String {
-    String::from("bash")
+    String::from("powershell.exe")
 }
 
 fn default_is_true() -> bool {
@@ -2,79 +2,761 @@
 //! for a typewriter system and all
 //! its referenced files to the currnet system
 
-use anyhow::bail;
+use std::{
+    collections::{HashMap, HashSet},
+    path::{Component, Path, PathBuf},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use ansi_term::Color::{Black, White};
+use anyhow::{Context, bail};
+use chrono::DateTime;
 use inquire::Confirm;
-use log::info;
-use std::path::PathBuf;
+use log::{error, info, warn};
 
 use crate::{
-    apply::{apply, hooks::HookStrategy, strategy::ApplyStrategy, variables::VariableApplying},
+    apply::{
+        FileApplyResult, FileApplyStatus, apply,
+        checkdiff::{CheckDiffApplying, FileCheckDiffStrategy},
+        checkpoint,
+        config_diff::diff_configs,
+        destination_metadata_dirs, history,
+        hooks::HookStrategy,
+        metadata_dirs,
+        report::write_dry_run_report,
+        skip_unchanged_files,
+        strategy::ApplyStrategy,
+        tempcopy::TemporaryCopyStrategy,
+        variables::VariableApplying,
+    },
+    args::{ApplyArgs, TagFilterMode},
     cleanpath::CleanPath,
-    config::ROOT_CONFIG,
-    parse_config::parse_config,
+    config::{Config, ROOT_CONFIG},
+    file::TrackedFileList,
+    log::paint,
+    parse_config::load_config,
+    vars::resolve_variable_references,
 };
 
+/// Prints the name and resolved value of every variable
+/// sorted by name, redacting the sensitive ones.
+fn print_resolved_variables(
+    var_map: &HashMap<String, String>,
+    sensitive_variables: &HashSet<String>,
+) {
+    let mut variables: Vec<_> = var_map.iter().collect();
+    variables.sort();
+
+    for (name, value) in variables {
+        let value = if sensitive_variables.contains(name) {
+            "<redacted>"
+        } else {
+            value.as_str()
+        };
+        println!("{} = {}", paint(White.bold(), name), value);
+    }
+}
+
 /// Questions the user whether or not to continue the apply based on
 /// the configuration
-fn continue_apply_prompt(num_applications: usize) -> anyhow::Result<bool> {
-    if !ROOT_CONFIG.get_config().apply.confirm_apply {
+fn continue_apply_prompt(num_applications: usize, config_file: &str) -> anyhow::Result<bool> {
+    let apply_config = &ROOT_CONFIG.get_config().apply;
+
+    if !apply_config.confirm_apply {
         info!("Running {} apply operations", num_applications);
         return Ok(true);
     }
 
-    Ok(
-        Confirm::new(format!("Run {} apply operations?", num_applications).as_str())
-            .with_default(true)
-            .prompt()?,
+    let prompt = match &apply_config.confirmation_prompt_format {
+        Some(format) => format
+            .replace("{count}", &num_applications.to_string())
+            .replace("{config_file}", config_file)
+            .replace(
+                "{metadata_dir}",
+                &apply_config.apply_metadata_dir.to_string_lossy(),
+            ),
+        None => format!("Run {} apply operations?", num_applications),
+    };
+
+    Ok(Confirm::new(prompt.as_str()).with_default(true).prompt()?)
+}
+
+/// Overrides options in the global config with
+/// the ones supplied through the command line
+fn override_config(config: &mut Config, args: &ApplyArgs) -> anyhow::Result<()> {
+    // Profile first, so flags still take precedence over it
+    if let Some(profile) = &args.profile {
+        info!("Using profile {} for this apply through --profile", profile);
+        config.apply_profile(profile)?;
+    }
+
+    if args.no_hooks {
+        info!("Hooks disabled for this apply through --no-hooks");
+        config.hooks.hooks_enabled = false;
+    }
+
+    if args.no_checkdiff {
+        info!("Checkdiff disabled for this apply through --no-checkdiff");
+        config.apply.checkdiff_strategy = FileCheckDiffStrategy::Disabled;
+    }
+
+    if args.no_backup {
+        warn!(
+            "Temporary backups disabled for this apply through --no-backup, files will NOT be rolled back if the apply fails"
+        );
+        config.apply.temp_copy_strategy = TemporaryCopyStrategy::Disabled;
+    }
+
+    if let Some(max_hook_output_size) = args.max_hook_output_size {
+        config.hooks.max_hook_output_size = Some(max_hook_output_size);
+    }
+
+    if args.no_variable_validation {
+        info!("Variable validation disabled for this apply through --no-variable-validation");
+        config.variables.validate_variables = false;
+    }
+
+    if args.force {
+        warn!(
+            "Applying without confirmation and overwriting destinations changed since the last apply through --force"
+        );
+        config.apply.confirm_apply = false;
+        config.apply.overwrite_changed = true;
+    }
+
+    // Metadata of the apply is kept in the output directory too, so
+    // nothing outside of it is written to or read for the apply
+    if let Some(output_dir) = &args.output_dir {
+        config.apply.apply_metadata_dir = path_in_output_dir(
+            &output_dir.clean_path()?,
+            &config.apply.apply_metadata_dir.clean_path()?,
+        );
+    }
+
+    if args.interactive {
+        info!("Confirming the apply and every shell command through --interactive");
+        config.apply.confirm_apply = true;
+        config.commands.confirm_shell_commands = true;
+    }
+
+    Ok(())
+}
+
+/// Path of the provided absolute path inside of the output directory, e.g
+/// /home/user/file is <output_dir>/home/user/file. Only the normal components
+/// are kept, so the root (and the drive prefix on Windows) is left out.
+fn path_in_output_dir(output_dir: &Path, path: &Path) -> PathBuf {
+    let mut sandboxed_path = output_dir.to_path_buf();
+    sandboxed_path.extend(
+        path.components()
+            .filter(|component| matches!(component, Component::Normal(_))),
+    );
+    sandboxed_path
+}
+
+/// Orders the strategies by their names in the configured
+/// order, which has to contain every strategy exactly once.
+fn order_strategies<'a>(
+    strategies: Vec<&'a dyn ApplyStrategy>,
+    order: &[String],
+) -> anyhow::Result<Vec<&'a dyn ApplyStrategy>> {
+    let mut registry: HashMap<&str, &dyn ApplyStrategy> = strategies
+        .into_iter()
+        .map(|strategy| (strategy.name(), strategy))
+        .collect();
+
+    let mut ordered: Vec<&dyn ApplyStrategy> = Vec::new();
+    for name in order {
+        let Some(strategy) = registry.remove(name.as_str()) else {
+            if ordered.iter().any(|strategy| strategy.name() == name) {
+                bail!(
+                    "Strategy {} is listed more than once in apply_strategy_order",
+                    name
+                );
+            }
+            bail!("Unknown strategy {} in apply_strategy_order", name);
+        };
+        ordered.push(strategy);
+    }
+
+    if !registry.is_empty() {
+        bail!(
+            "Strategies {:?} are missing from apply_strategy_order, every strategy has to be listed",
+            registry.keys().collect::<Vec<_>>()
+        );
+    }
+
+    Ok(ordered)
+}
+
+/// Builds the strategies for the apply in the configured order
+fn build_strategies<'a>(
+    config: &'a Config,
+    var_strategy: &'a VariableApplying,
+    checkdiff_strategy: &'a CheckDiffApplying,
+    hook_strategy: &'a HookStrategy,
+) -> anyhow::Result<Vec<&'a dyn ApplyStrategy>> {
+    // ensure order is correct or bad things will happen !!
+    order_strategies(
+        vec![
+            &config.apply.file_permission_strategy,
+            var_strategy,
+            checkdiff_strategy,
+            &config.apply.temp_copy_strategy,
+            hook_strategy,
+        ],
+        &config.apply.apply_strategy_order,
     )
 }
 
-pub fn apply_command(file: String, section: String) -> anyhow::Result<()> {
-    // Validate file path
-    let path = PathBuf::from(file).clean_path()?;
+/// Prints the outcome of every file of the apply as a table
+fn print_results_table(results: &[FileApplyResult]) {
+    if results.is_empty() {
+        return;
+    }
+
+    println!(
+        "{}",
+        paint(
+            White.bold(),
+            format!("{:<8} {:>8}  {}", "STATUS", "TIME", "DESTINATION")
+        )
+    );
+
+    for result in results {
+        let (status, detail) = match &result.status {
+            FileApplyStatus::Applied => ("APPLIED", None),
+            FileApplyStatus::Skipped { reason } => ("SKIPPED", Some(reason)),
+            FileApplyStatus::Failed { error } => ("FAILED", Some(error)),
+        };
 
-    // Parse configs to config structs.
-    let (root, configs) = parse_config(path, section)?;
+        println!(
+            "{} {:>6}ms  {:?} {}",
+            paint(White.bold(), format!("{:<8}", status)),
+            result.duration_ms,
+            result.destination,
+            paint(
+                Black.dimmed(),
+                detail
+                    .map(|detail| format!("[{}]", detail))
+                    .unwrap_or_default()
+            )
+        );
+    }
+}
 
-    // Fill in global root config from root
-    let global_config = root.config.unwrap_or_default();
-    ROOT_CONFIG.set_config(global_config);
+/// Prints a summary of what the apply is configured with and asks
+/// the user whether to continue with it, returning their answer.
+fn config_summary_prompt(
+    files_count: usize,
+    variables_count: usize,
+    hooks_count: usize,
+    strategies: &[&dyn ApplyStrategy],
+) -> anyhow::Result<bool> {
+    let summary = [
+        ("Files", files_count.to_string()),
+        ("Variables", variables_count.to_string()),
+        ("Hooks", hooks_count.to_string()),
+    ];
+
+    for (name, value) in summary {
+        println!("{} {}", paint(White.bold(), format!("{:<12}", name)), value);
+    }
+
+    println!("{}", paint(White.bold(), "Strategies"));
+    for strategy in strategies {
+        println!(
+            "  {} {}",
+            paint(White.bold(), format!("{:<10}", strategy.name())),
+            strategy.variant()
+        );
+    }
+
+    Ok(Confirm::new("Continue with this configuration?")
+        .with_default(true)
+        .prompt()?)
+}
+
+/// Prints whether applying every file would change its destination
+/// by comparing the hash of the rendered file with the current
+/// destination, without writing anything.
+fn print_dry_run_summary(
+    files: &TrackedFileList,
+    var_strategy: &VariableApplying,
+) -> anyhow::Result<()> {
+    let hash_strategy = FileCheckDiffStrategy::XXHashDiff;
+
+    for file in files.iter() {
+        let rendered = var_strategy.render_file(file)?;
+
+        let status = if !file.destination.exists() {
+            "CREATE"
+        } else if hash_strategy.hash_file(&file.destination)? == hash_strategy.hash_bytes(&rendered)
+        {
+            "SAME"
+        } else {
+            "CHANGE"
+        };
+
+        println!(
+            "{} {:?} -> {:?} {}",
+            paint(White.bold(), format!("{:<8}", status)),
+            file.file,
+            file.destination,
+            paint(Black.dimmed(), format!("[{} bytes]", rendered.len()))
+        );
+    }
+
+    Ok(())
+}
+
+/// Sets TYPEWRITER_* environment variables describing this apply,
+/// so they are available to every hook and command variable.
+fn set_apply_env(args: &ApplyArgs, config: &Config) -> anyhow::Result<()> {
+    let apply_id = format!(
+        "{}-{}",
+        SystemTime::now().duration_since(UNIX_EPOCH)?.as_millis(),
+        std::process::id()
+    );
+
+    let apply_env = [
+        (
+            "TYPEWRITER_CONFIG_PATH",
+            args.file.clean_path()?.display().to_string(),
+        ),
+        (
+            "TYPEWRITER_METADATA_DIR",
+            config
+                .apply
+                .apply_metadata_dir
+                .clean_path()?
+                .display()
+                .to_string(),
+        ),
+        ("TYPEWRITER_APPLY_ID", apply_id),
+        ("TYPEWRITER_VERSION", env!("CARGO_PKG_VERSION").to_string()),
+    ];
+
+    for (name, value) in apply_env {
+        // SAFETY: No other threads are running before the apply starts
+        unsafe { std::env::set_var(name, value) };
+    }
+
+    Ok(())
+}
+
+/// Sets TYPEWRITER_FILES_COUNT to the amount of files the
+/// apply applies, once they have been filtered by the flags.
+fn set_files_count_env(files_count: usize) {
+    // SAFETY: No other threads are running before the apply starts
+    unsafe { std::env::set_var("TYPEWRITER_FILES_COUNT", files_count.to_string()) };
+}
+
+pub fn apply_command(args: ApplyArgs) -> anyhow::Result<()> {
+    // Set the environment variables given inline first so
+    // they are seen by the config and the environment flags
+    for entry in &args.env {
+        let Some((key, value)) = entry.split_once('=') else {
+            bail!(
+                "Environment variable {} given through --env should be KEY=VALUE",
+                entry
+            );
+        };
+
+        // Setting the environment panics on these rather than erroring
+        if key.is_empty() || key.contains(['=', '\0']) || value.contains('\0') {
+            bail!(
+                "Environment variable {:?} given through --env should have a non-empty KEY without '=' or NUL characters, and a VALUE without NUL characters",
+                entry
+            );
+        }
+
+        // SAFETY: No other threads are running before the config is parsed
+        unsafe { std::env::set_var(key, value) };
+    }
+
+    // Parse configs and grab data flattened into a list
+    let (mut total_files_list, total_variables_list, total_hooks_list) =
+        load_config(&args.file, args.section.clone(), |config| {
+            override_config(config, &args)
+        })?;
 
     let config = ROOT_CONFIG.get_config();
 
-    // Grab data flattened into a list
-    let (mut total_files_list, mut total_variables_list, mut total_hooks_list) =
-        configs.flatten_data();
-    total_files_list.extend(root.files.0.into_iter());
-    total_variables_list.extend(root.variables.0.into_iter());
-    total_hooks_list.extend(root.hooks.0.into_iter());
+    // Describe the apply to child processes
+    set_apply_env(&args, config)?;
 
-    // Deal with variables first
-    let var_map = total_variables_list.to_map()?;
-    let var_strategy = VariableApplying::new(config.variables.variable_strategy, var_map);
+    // Only checking the configuration is valid, the stages of hooks and
+    // the strategy order are validated but nothing is resolved or ran.
+    if args.config_check_only {
+        let var_strategy =
+            VariableApplying::new(config.variables.variable_strategy, HashMap::new());
+        let hook_strategy = HookStrategy::new(total_hooks_list)?;
+        let checkdiff_strategy =
+            CheckDiffApplying::new(&config.apply.checkdiff_strategy, &hook_strategy);
+        build_strategies(config, &var_strategy, &checkdiff_strategy, &hook_strategy)?;
+
+        info!(
+            "Configuration {:?} is valid ({} files, {} variables)",
+            args.file,
+            total_files_list.len(),
+            total_variables_list.len()
+        );
+        return Ok(());
+    }
+
+    // Only comparing the configuration with another one
+    if let Some(other_file) = &args.config_diff {
+        if !diff_configs(
+            &args.file,
+            &total_files_list,
+            &total_variables_list,
+            &total_hooks_list,
+            other_file,
+            args.section.clone(),
+        )? {
+            bail!(
+                "Configuration {:?} is different from {:?}",
+                args.file,
+                other_file
+            );
+        }
+
+        info!(
+            "Configuration {:?} is equivalent to {:?}",
+            args.file, other_file
+        );
+        return Ok(());
+    }
+
+    // Only running global hooks, no files are touched so
+    // none of the file strategies or variables are needed.
+    if args.only_hooks {
+        info!("Running only global hooks through --only-hooks, no files will be applied");
+        let hook_strategy = HookStrategy::new(total_hooks_list)?;
+        set_files_count_env(0);
+        apply(TrackedFileList::default(), vec![&hook_strategy])?;
+        return Ok(());
+    }
+
+    // Sandbox every destination in the output directory
+    if let Some(output_dir) = &args.output_dir {
+        let output_dir = output_dir.clean_path()?;
+        info!(
+            "Writing all destinations under {:?} through --output-dir",
+            output_dir
+        );
+
+        for file in total_files_list.iter_mut() {
+            file.destination = path_in_output_dir(&output_dir, &file.destination);
+
+            if let Some(metadata_dir) = &file.metadata_dir {
+                file.metadata_dir =
+                    Some(path_in_output_dir(&output_dir, &metadata_dir.clean_path()?));
+            }
+
+            if let Some(backup_destination) = &file.backup_destination {
+                file.backup_destination = Some(path_in_output_dir(&output_dir, backup_destination));
+            }
+        }
+    }
+
+    // Metadata directories of every file, before any are filtered out
+    let all_metadata_dirs = metadata_dirs(&total_files_list);
+
+    // Skip the files completed by a previously failed apply
+    if args.resume {
+        let completed = checkpoint::read_checkpoint(&all_metadata_dirs)?;
+        total_files_list.retain(|file| {
+            let is_completed = completed.contains(&file.destination);
+            if is_completed {
+                info!(
+                    "Skipping {:?} since it was already applied by the resumed apply",
+                    file.destination
+                );
+            }
+            !is_completed
+        });
+    }
+
+    // Only files with the requested tags
+    if !args.tags.is_empty() {
+        total_files_list.retain(|file| {
+            let has_tag = |tag: &String| file.tags.contains(tag);
+            let is_selected = match args.tag_filter_mode {
+                TagFilterMode::Any => args.tags.iter().any(has_tag),
+                TagFilterMode::All => args.tags.iter().all(has_tag),
+            };
+
+            if !is_selected {
+                info!(
+                    "Skipping {:?} since it does not match the tags {:?}",
+                    file.destination, args.tags
+                );
+            }
+            is_selected
+        });
+    }
+
+    // Only files in the requested group, or in no group
+    if let Some(only_group) = &args.only_group {
+        total_files_list.retain(|file| match &file.group {
+            Some(group) if group != only_group => {
+                info!(
+                    "Skipping {:?} since it is in group {} rather than {}",
+                    file.destination, group, only_group
+                );
+                false
+            }
+            _ => true,
+        });
+    }
+
+    // Without files in the excluded groups
+    if !args.exclude_group.is_empty() {
+        total_files_list.retain(|file| match &file.group {
+            Some(group) if args.exclude_group.contains(group) => {
+                info!(
+                    "Skipping {:?} since its group {} is excluded",
+                    file.destination, group
+                );
+                false
+            }
+            _ => true,
+        });
+    }
+
+    // Skip files which were applied after the provided time
+    if let Some(since) = &args.since {
+        let since = DateTime::parse_from_rfc3339(since)
+            .with_context(|| format!("While trying to parse --since datetime {}", since))?;
+        let history = history::read_history(&all_metadata_dirs)?;
+
+        total_files_list.retain(|file| match history.get(&file.destination) {
+            Some(last_applied) if *last_applied >= since.timestamp() => {
+                info!(
+                    "Skipping {:?} since it was already applied after {}",
+                    file.destination, since
+                );
+                false
+            }
+            _ => true,
+        });
+    }
+
+    // Counted once every file not applied is filtered out
+    set_files_count_env(total_files_list.len());
+
+    // Only resolving the variables for debugging them
+    if args.dry_run_vars {
+        let sensitive_variables = total_variables_list
+            .iter()
+            .filter(|variable| variable.sensitive)
+            .map(|variable| variable.name.clone())
+            .collect();
+        print_resolved_variables(&total_variables_list.to_map()?, &sensitive_variables);
+        return Ok(());
+    }
+
+    // Only show the strategies, variables aren't
+    // needed for that so don't resolve them.
+    if args.list_strategies {
+        let var_strategy =
+            VariableApplying::new(config.variables.variable_strategy, HashMap::new());
+        let hook_strategy = HookStrategy::new(total_hooks_list)?;
+        let checkdiff_strategy =
+            CheckDiffApplying::new(&config.apply.checkdiff_strategy, &hook_strategy);
+
+        for (index, strategy) in
+            build_strategies(config, &var_strategy, &checkdiff_strategy, &hook_strategy)?
+                .iter()
+                .enumerate()
+        {
+            println!(
+                "{}. {}: {}",
+                index + 1,
+                paint(White.bold(), strategy.name()),
+                strategy.variant()
+            );
+        }
+        return Ok(());
+    }
+
+    // Counted before the lists are consumed for --config-summary
+    let variables_count = total_variables_list.len();
+    let hooks_count = total_hooks_list.len();
+
+    // Deal with variables first, unless they're not used at all
+    let var_map = if config.variables.preload_skip {
+        HashMap::new()
+    } else {
+        total_variables_list.to_map()?
+    };
+    let mut var_strategy = VariableApplying::new(config.variables.variable_strategy, var_map);
+
+    // Snapshot what is written to the destinations
+    if let Some(save_rendered) = &args.save_rendered {
+        var_strategy.save_rendered_to(save_rendered.clean_path()?);
+    }
 
     // Create hook strategy
     let hook_strategy = HookStrategy::new(total_hooks_list)?;
 
+    let checkdiff_strategy =
+        CheckDiffApplying::new(&config.apply.checkdiff_strategy, &hook_strategy);
+
+    let strategies = build_strategies(config, &var_strategy, &checkdiff_strategy, &hook_strategy)?;
+
+    // Skip files which haven't changed since the last apply
+    if args.skip_unchanged {
+        skip_unchanged_files(&mut total_files_list, &var_strategy, &strategies)?;
+    }
+
+    // Only show what would change, after validating variables
+    if args.dry_run {
+        var_strategy.run_before_apply(&mut total_files_list)?;
+        print_dry_run_summary(&total_files_list, &var_strategy)?;
+
+        // Report what would be done as well
+        if let Some(report_path) = &args.dry_run_report {
+            write_dry_run_report(
+                report_path,
+                &total_files_list,
+                &var_strategy,
+                &hook_strategy,
+            )?;
+            info!("Wrote dry run report to {:?}", report_path);
+        }
+
+        info!(
+            "Dry run of {} files, no files were applied",
+            total_files_list.len()
+        );
+        return Ok(());
+    }
+
     // Nothing to apply to case.
     if total_files_list.len() < 1 {
         info!("No files referenced to apply to, no operation.");
+        if args.resume {
+            checkpoint::remove_checkpoint(&all_metadata_dirs)?;
+        }
         return Ok(());
     }
 
-    if !continue_apply_prompt(total_files_list.len())? {
+    // Safety limit on the amount of files touched
+    if let Some(max_files) = args.max_files {
+        if total_files_list.len() > max_files {
+            bail!(
+                "Apply would touch {} files, exceeding the safety limit of {}. Pass --max-files {} to override.",
+                total_files_list.len(),
+                max_files,
+                total_files_list.len()
+            );
+        }
+    }
+
+    // Verifying needs the checksums stored by checkdiff
+    if args.verify_after {
+        if let FileCheckDiffStrategy::Disabled = config.apply.checkdiff_strategy {
+            bail!("--verify-after requires a checkdiff_strategy other than disabled");
+        }
+    }
+
+    if let Some(message) = &config.apply.pre_apply_message {
+        println!(
+            "{}",
+            resolve_variable_references(message, var_strategy.var_map())
+        );
+    }
+
+    if args.config_summary
+        && !config_summary_prompt(
+            total_files_list.len(),
+            variables_count,
+            hooks_count,
+            &strategies,
+        )?
+    {
         bail!("Aborting apply operation");
     }
 
-    // ensure order is correct or bad things will happen !!
-    let strategies: Vec<&dyn ApplyStrategy> = vec![
-        &config.apply.file_permission_strategy,
-        &var_strategy,
-        &config.apply.checkdiff_strategy,
-        &config.apply.temp_copy_strategy,
-        &hook_strategy,
-    ];
+    if !continue_apply_prompt(total_files_list.len(), &args.file)? {
+        bail!("Aborting apply operation");
+    }
+
+    // Fresh apply, start from a fresh checkpoint too
+    if !args.resume {
+        checkpoint::remove_checkpoint(&all_metadata_dirs)?;
+    }
+
+    // Metadata directories checksums are stored in, for verifying
+    let checksum_dirs = metadata_dirs(&total_files_list);
+
+    // Metadata directories the history of each destination is kept in
+    let destination_dirs = destination_metadata_dirs(&total_files_list);
 
     // Run apply
-    apply(total_files_list, strategies)
+    let report = apply(total_files_list, strategies)?;
+    history::record_applied(&report.applied, &destination_dirs)?;
+    print_results_table(&report.results);
+
+    // Files which failed were skipped over, summarise them
+    if !report.errors.is_empty() {
+        for apply_error in &report.errors {
+            error!(
+                "Failed to apply {:?}: {:?}",
+                apply_error.destination, apply_error.error
+            );
+        }
+
+        bail!(
+            "{} file(s) failed to apply ({} applied, {} skipped), use --resume to retry them",
+            report.errors.len(),
+            report.applied.len(),
+            report.skipped.len()
+        );
+    }
+
+    // Resumed apply is now fully done
+    if args.resume {
+        checkpoint::remove_checkpoint(&all_metadata_dirs)?;
+    }
+
+    // Check the files were written correctly
+    if args.verify_after {
+        let mismatched = config
+            .apply
+            .checkdiff_strategy
+            .verify_checksums(&report.applied, &checksum_dirs)?;
+
+        for destination in &mismatched {
+            error!(
+                "Checksum of {:?} does not match the one stored after applying it",
+                destination
+            );
+        }
+
+        if !mismatched.is_empty() {
+            bail!(
+                "Verification failed for {} of {} applied file(s)",
+                mismatched.len(),
+                report.applied.len()
+            );
+        }
+
+        info!(
+            "Verified checksums of {} applied file(s)",
+            report.applied.len()
+        );
+    }
+
+    if let Some(message) = &config.apply.post_apply_message {
+        println!(
+            "{}",
+            resolve_variable_references(message, var_strategy.var_map())
+        );
+    }
+
+    Ok(())
 }
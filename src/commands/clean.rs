@@ -0,0 +1,216 @@
+//! Purges the files an apply leaves behind in the
+//! metadata directories of a typewriter system
+
+use ansi_term::Color::{Black, White};
+use anyhow::{Context, bail};
+use inquire::Confirm;
+use log::info;
+use std::{fs, path::PathBuf};
+
+use crate::{
+    apply::{metadata_dirs, tempcopy::ROTATED_DIR_NAME},
+    cleanpath::CleanPath,
+    config::ROOT_CONFIG,
+    log::paint,
+    parse_config::load_config,
+};
+
+/// Which of the files in the metadata directories to delete
+#[derive(Clone, Copy)]
+enum CleanTarget {
+    // Every file
+    All,
+
+    // Only temporary copies of destinations
+    Backups,
+
+    // Only the checksum storage file
+    Checkdiff,
+}
+
+/// Whether the file in a metadata directory is one the target deletes
+fn is_clean_target(file_name: &str, target: CleanTarget) -> bool {
+    let apply_config = &ROOT_CONFIG.get_config().apply;
+
+    // Temporary copies are named after their absolute destination, so they
+    // start with the delimiter its leading separator was replaced with
+    let is_backup = file_name.starts_with(&apply_config.temp_copy_path_delim);
+
+    match target {
+        CleanTarget::All => true,
+        CleanTarget::Backups => is_backup,
+        CleanTarget::Checkdiff => file_name == apply_config.checkdiff_file_name,
+    }
+}
+
+/// Returns every rotated temporary copy kept in the metadata directory
+/// along with its size, and the directories they are kept in.
+fn rotated_temp_copies(
+    metadata_dir: &PathBuf,
+) -> anyhow::Result<(Vec<(PathBuf, u64)>, Vec<PathBuf>)> {
+    let rotated_dir = metadata_dir.join(ROTATED_DIR_NAME);
+    if !rotated_dir.is_dir() {
+        return Ok((Vec::new(), Vec::new()));
+    }
+
+    let mut copies = Vec::new();
+    let mut copy_dirs = Vec::new();
+
+    // Every destination has a directory of its own in there
+    for destination_dir in fs::read_dir(&rotated_dir).with_context(|| {
+        format!(
+            "While trying to read rotated temporary copy directory {:?}",
+            rotated_dir
+        )
+    })? {
+        let destination_dir = destination_dir?.path();
+        if !destination_dir.is_dir() {
+            continue;
+        }
+
+        for entry in fs::read_dir(&destination_dir).with_context(|| {
+            format!(
+                "While trying to read rotated temporary copy directory {:?}",
+                destination_dir
+            )
+        })? {
+            let entry = entry?;
+            let metadata = entry.metadata()?;
+            if metadata.is_file() {
+                copies.push((entry.path(), metadata.len()));
+            }
+        }
+
+        copy_dirs.push(destination_dir);
+    }
+
+    copy_dirs.push(rotated_dir);
+    Ok((copies, copy_dirs))
+}
+
+/// Questions the user whether or not to continue the clean based on
+/// the configuration
+fn continue_clean_prompt(num_files: usize, num_bytes: u64) -> anyhow::Result<bool> {
+    if !ROOT_CONFIG.get_config().apply.confirm_apply {
+        info!("Deleting {} files ({} bytes)", num_files, num_bytes);
+        return Ok(true);
+    }
+
+    Ok(Confirm::new(
+        format!(
+            "Delete {} files ({} bytes) from the metadata directories?",
+            num_files, num_bytes
+        )
+        .as_str(),
+    )
+    .with_default(false)
+    .prompt()?)
+}
+
+pub fn clean_command(
+    file: String,
+    section: String,
+    only_backups: bool,
+    only_checkdiff: bool,
+) -> anyhow::Result<()> {
+    let target = if only_backups {
+        CleanTarget::Backups
+    } else if only_checkdiff {
+        CleanTarget::Checkdiff
+    } else {
+        CleanTarget::All
+    };
+
+    // Config is needed for the metadata directories
+    let (total_files_list, _, _) = load_config(&file, section, |_| Ok(()))?;
+
+    // Never delete configuration or source files, in case
+    // a metadata directory is shared with them
+    let mut protected_files = vec![file.clean_path()?];
+    for tracked_file in total_files_list.iter() {
+        protected_files.push(tracked_file.src.clone());
+        protected_files.push(tracked_file.file.clone());
+    }
+
+    // Every file to delete along with its size
+    let mut to_delete: Vec<(PathBuf, u64)> = Vec::new();
+
+    // Directories of rotated temporary copies, removed once they're empty
+    let mut rotated_dirs: Vec<PathBuf> = Vec::new();
+    for metadata_dir in metadata_dirs(&total_files_list) {
+        if !metadata_dir.exists() {
+            info!(
+                "Metadata directory {:?} does not exist, nothing to clean in it.",
+                metadata_dir
+            );
+            continue;
+        }
+
+        let entries = fs::read_dir(&metadata_dir).with_context(|| {
+            format!("While trying to read metadata directory {:?}", metadata_dir)
+        })?;
+
+        for entry in entries {
+            let entry = entry?;
+            let metadata = entry.metadata()?;
+
+            if !metadata.is_file()
+                || !is_clean_target(&entry.file_name().to_string_lossy(), target)
+                || protected_files.contains(&entry.path())
+            {
+                continue;
+            }
+
+            println!(
+                "[{}] {:?} {}",
+                paint(White.bold(), "DELETE"),
+                entry.path(),
+                paint(Black.dimmed(), format!("[{} bytes]", metadata.len()))
+            );
+            to_delete.push((entry.path(), metadata.len()));
+        }
+
+        // Rotated temporary copies are kept in directories of their own
+        if matches!(target, CleanTarget::All | CleanTarget::Backups) {
+            let (copies, copy_dirs) = rotated_temp_copies(&metadata_dir)?;
+            for (path, size) in copies {
+                println!(
+                    "[{}] {:?} {}",
+                    paint(White.bold(), "DELETE"),
+                    path,
+                    paint(Black.dimmed(), format!("[{} bytes]", size))
+                );
+                to_delete.push((path, size));
+            }
+            rotated_dirs.extend(copy_dirs);
+        }
+    }
+
+    // Nothing to delete case.
+    if to_delete.is_empty() {
+        info!("No files found to clean, no operation.");
+        return Ok(());
+    }
+
+    let num_bytes: u64 = to_delete.iter().map(|(_, size)| size).sum();
+    if !continue_clean_prompt(to_delete.len(), num_bytes)? {
+        bail!("Aborting clean operation");
+    }
+
+    for (path, _) in to_delete.iter() {
+        fs::remove_file(path).with_context(|| format!("While trying to delete file {:?}", path))?;
+    }
+
+    // Innermost first, only empty directories can be removed
+    // so anything else left in them is kept along with them
+    for dir in rotated_dirs.iter() {
+        let _ = fs::remove_dir(dir);
+    }
+
+    info!(
+        "Deleted {} files, freeing {} bytes",
+        to_delete.len(),
+        num_bytes
+    );
+    Ok(())
+}
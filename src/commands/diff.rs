@@ -0,0 +1,65 @@
+//! Shows the diff between the rendered sources of a
+//! typewriter system and their current destinations
+
+use log::info;
+use similar::TextDiff;
+use std::{collections::HashMap, fs};
+
+use crate::{
+    apply::variables::VariableApplying, commands::show_patch::print_colored_patch,
+    config::ROOT_CONFIG, parse_config::load_config,
+};
+
+pub fn diff_command(file: String, section: String) -> anyhow::Result<()> {
+    let (total_files_list, total_variables_list, _) = load_config(&file, section, |_| Ok(()))?;
+    let config = ROOT_CONFIG.get_config();
+
+    // Sources are rendered in memory the same way applying does
+    let var_map = if config.variables.preload_skip {
+        HashMap::new()
+    } else {
+        total_variables_list.to_map()?
+    };
+    let var_strategy = VariableApplying::new(config.variables.variable_strategy, var_map);
+
+    for file in total_files_list.iter() {
+        if !file.file.exists() {
+            info!("Skipping {:?} since its source does not exist", file.file);
+            continue;
+        }
+
+        let rendered = var_strategy.render_file(file)?;
+
+        // Destinations which don't exist yet are compared as empty
+        let current = if file.destination.exists() {
+            fs::read(&file.destination).ok()
+        } else {
+            Some(Vec::new())
+        };
+
+        let (Some(current), Ok(rendered)) = (current, String::from_utf8(rendered)) else {
+            info!(
+                "Skipping {:?} since it could not be read as text",
+                file.destination
+            );
+            continue;
+        };
+        let current = String::from_utf8_lossy(&current);
+
+        if current == rendered {
+            info!("No changes to {:?}", file.destination);
+            continue;
+        }
+
+        let destination = file.destination.to_string_lossy();
+        let diff = TextDiff::from_lines(current.as_ref(), rendered.as_str());
+        print_colored_patch(
+            &diff
+                .unified_diff()
+                .header(&destination, &destination)
+                .to_string(),
+        );
+    }
+
+    Ok(())
+}
@@ -1,18 +1,29 @@
 //! Initialises a typewriter system
 //! with a basic configuration file
 
-use anyhow::bail;
-use inquire::Confirm;
+use anyhow::{Context, bail};
+use inquire::{Confirm, Select, Text};
 use log::info;
-use std::{fs, path::PathBuf};
+use std::{
+    fs::{self, OpenOptions},
+    io::{IsTerminal, Write},
+    path::PathBuf,
+};
+
+use crate::{
+    apply::variables::VariableApplyingStrategy,
+    config::{Config, Typewriter},
+};
 
 /// Default file just include it as a str..
 const DEFAULT_TEMPLATE: &'static str = include_str!("../default.toml");
 
-pub fn init_command(file: String) -> anyhow::Result<()> {
-    // Path to the file
-    let path = PathBuf::from(file);
+/// Templates the wizard can start the configuration from
+const TEMPLATES: [&'static str; 2] = ["default", "empty"];
 
+/// Prompts the user whether to overwrite the path if it exists
+/// already, erroring if they don't want it to be overwritten.
+fn confirm_overwrite(path: &PathBuf) -> anyhow::Result<()> {
     // Whether or not we should generate the output file
     // set to false to disable at the end
     let mut generate_output = true;
@@ -29,6 +40,120 @@ pub fn init_command(file: String) -> anyhow::Result<()> {
         bail!("Not generating template to {:?}, file already exists", path);
     }
 
+    Ok(())
+}
+
+/// Adds the metadata directory to the .gitignore next
+/// to the configuration file, so it isn't committed.
+fn ignore_metadata_dir(path: &PathBuf, metadata_dir: &str) -> anyhow::Result<()> {
+    let gitignore_path = path
+        .parent()
+        .map(|parent| parent.join(".gitignore"))
+        .unwrap_or_else(|| PathBuf::from(".gitignore"));
+
+    let existing = if gitignore_path.exists() {
+        fs::read_to_string(&gitignore_path)
+            .with_context(|| format!("While trying to read {:?}", gitignore_path))?
+    } else {
+        String::new()
+    };
+
+    // Already ignored by a previous init
+    if existing.lines().any(|line| line.trim() == metadata_dir) {
+        info!("{} is already in {:?}", metadata_dir, gitignore_path);
+        return Ok(());
+    }
+
+    let mut gitignore = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(&gitignore_path)
+        .with_context(|| format!("While trying to open {:?}", gitignore_path))?;
+
+    // Don't join the entry onto the last line
+    let separator = if existing.is_empty() || existing.ends_with('\n') {
+        ""
+    } else {
+        "\n"
+    };
+
+    writeln!(gitignore, "{}{}", separator, metadata_dir)
+        .with_context(|| format!("While trying to write to {:?}", gitignore_path))?;
+
+    info!("Added {} to {:?}", metadata_dir, gitignore_path);
+    Ok(())
+}
+
+/// Asks the user how typewriter should be set up
+/// and writes a configuration file built from it.
+fn init_wizard(file: String) -> anyhow::Result<()> {
+    let template = Select::new(
+        "Which template should the config start from?",
+        TEMPLATES.to_vec(),
+    )
+    .prompt()?;
+
+    let mut typewriter: Typewriter = match template {
+        "default" => toml::from_str(DEFAULT_TEMPLATE)
+            .with_context(|| "While trying to parse the default template")?,
+        _ => Typewriter::default(),
+    };
+
+    let path = PathBuf::from(
+        Text::new("Configuration file name")
+            .with_default(&file)
+            .prompt()?,
+    );
+    confirm_overwrite(&path)?;
+
+    let mut config = Config::default();
+
+    let metadata_dir = Text::new("Directory to keep metadata (temporary copies, checksums) in")
+        .with_default(&config.apply.apply_metadata_dir.to_string_lossy())
+        .prompt()?;
+    config.apply.apply_metadata_dir = PathBuf::from(&metadata_dir);
+
+    if !Confirm::new("Enable variable preprocessing?")
+        .with_default(true)
+        .prompt()?
+    {
+        config.variables.variable_strategy = VariableApplyingStrategy::Disabled;
+    }
+
+    config.hooks.hooks_enabled = Confirm::new("Enable hooks?").with_default(true).prompt()?;
+
+    let git_integration = Confirm::new("Add the metadata directory to .gitignore?")
+        .with_default(true)
+        .prompt()?;
+
+    config.apply.confirm_apply = !Confirm::new("Apply without confirmation? (for automation)")
+        .with_default(false)
+        .prompt()?;
+
+    typewriter.config = Some(config);
+
+    let contents = toml::to_string(&typewriter)
+        .with_context(|| "While trying to serialize the configuration")?;
+    fs::write(&path, contents)?;
+    info!("Wrote configuration file to {:?}", path);
+
+    if git_integration {
+        ignore_metadata_dir(&path, &metadata_dir)?;
+    }
+
+    Ok(())
+}
+
+pub fn init_command(file: String) -> anyhow::Result<()> {
+    // Ask how to set up typewriter when there's someone to answer
+    if std::io::stdin().is_terminal() {
+        return init_wizard(file);
+    }
+
+    // Path to the file
+    let path = PathBuf::from(file);
+    confirm_overwrite(&path)?;
+
     // Write default template
     fs::write(&path, DEFAULT_TEMPLATE)?;
     info!("Wrote default template file to {:?}", path);
@@ -0,0 +1,212 @@
+//! Shows the resolved values of the variables of a typewriter
+//! system, and how a single variable was resolved for debugging
+
+use ansi_term::Color::{Black, White};
+use anyhow::Context;
+use std::{collections::HashMap, path::PathBuf};
+
+use crate::{
+    log::paint,
+    parse_config::load_config,
+    vars::{VariableType, extract_variable_references, resolve_variable_references},
+};
+
+/// Placeholder printed instead of sensitive values
+const REDACTED: &str = "<redacted>";
+
+/// What is known about a variable before it is resolved
+struct InspectedVariable {
+    // Name of the variable
+    name: String,
+
+    // Type of the variable
+    var_type: VariableType,
+
+    // Raw value as written in the configuration
+    value: String,
+
+    // Configuration file the variable was declared in
+    src: PathBuf,
+
+    // Command the value is post-processed with, if any
+    transform: Option<String>,
+
+    // Variables which are resolved before this one
+    depends_on: Vec<String>,
+
+    // Whether the value of the variable should be masked, for prompt
+    // and sensitive variables along with variables which depend
+    // on them (directly or not)
+    sensitive: bool,
+}
+
+/// Marks every variable which depends on a sensitive variable as sensitive,
+/// since the value of the sensitive one ends up in its value.
+fn propagate_sensitive(inspected_variables: &mut [InspectedVariable]) -> anyhow::Result<()> {
+    // Variables referenced in the value of each variable, along with explicit ones
+    let mut dependencies = Vec::new();
+    for variable in inspected_variables.iter() {
+        let mut references = extract_variable_references(&variable.value)?;
+        references.extend(variable.depends_on.iter().cloned());
+        dependencies.push(references);
+    }
+
+    let mut changed = true;
+    while changed {
+        changed = false;
+        for index in 0..inspected_variables.len() {
+            if inspected_variables[index].sensitive {
+                continue;
+            }
+
+            let depends_on_sensitive = dependencies[index].iter().any(|dependency| {
+                inspected_variables
+                    .iter()
+                    .any(|variable| &variable.name == dependency && variable.sensitive)
+            });
+
+            if depends_on_sensitive {
+                inspected_variables[index].sensitive = true;
+                changed = true;
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Prints a labelled line of the details of a variable
+fn print_detail(label: &str, value: &str) {
+    println!("  {:<16} {}", paint(White.bold(), label), value);
+}
+
+/// Prints the details of a single variable and the
+/// steps taken to resolve it to its final value
+fn print_variable_details(
+    variable: &InspectedVariable,
+    var_map: &HashMap<String, String>,
+    reveal: bool,
+) -> anyhow::Result<()> {
+    let mask = |value: &str| -> String {
+        if variable.sensitive && !reveal {
+            REDACTED.to_string()
+        } else {
+            value.to_string()
+        }
+    };
+
+    let final_value = var_map
+        .get(&variable.name)
+        .context("Variable was not resolved")?;
+
+    // References to other variables are substituted before anything else
+    let substituted_value = resolve_variable_references(&variable.value, var_map);
+
+    println!("{}", paint(White.bold(), &variable.name));
+    print_detail("type", variable.var_type.name());
+    print_detail("declared in", &format!("{:?}", variable.src));
+    print_detail("raw value", &mask(&variable.value));
+
+    if !variable.depends_on.is_empty() {
+        print_detail("depends on", &variable.depends_on.join(", "));
+    }
+
+    if substituted_value != variable.value {
+        print_detail("substituted", &mask(&substituted_value));
+    }
+
+    let resolution_step = match variable.var_type {
+        VariableType::Literal => None,
+        VariableType::Command => Some(("ran command", mask(&substituted_value))),
+        VariableType::Environment => Some(("read env var", substituted_value.clone())),
+        VariableType::JsonQuery | VariableType::TomlQuery => {
+            Some(("queried", substituted_value.clone()))
+        }
+        VariableType::File => Some(("read file", substituted_value.clone())),
+        VariableType::Prompt => Some(("prompted with", substituted_value.clone())),
+    };
+
+    if let Some((label, step)) = resolution_step {
+        print_detail(label, &step);
+    }
+
+    if let Some(transform) = &variable.transform {
+        print_detail("transformed by", transform);
+    }
+
+    print_detail("final value", &mask(final_value));
+
+    Ok(())
+}
+
+pub fn inspect_command(
+    file: String,
+    section: String,
+    name: Option<String>,
+    reveal: bool,
+) -> anyhow::Result<()> {
+    let (_, total_variables_list, _) = load_config(&file, section, |_| Ok(()))?;
+
+    // Details are taken before resolving, which consumes the variables
+    let mut inspected_variables: Vec<InspectedVariable> = total_variables_list
+        .iter()
+        .map(|variable| InspectedVariable {
+            name: variable.name.clone(),
+            var_type: variable.var_type,
+            value: variable.value.clone(),
+            src: variable.src.clone(),
+            transform: variable.transform.clone(),
+            depends_on: variable.depends_on.clone(),
+            // Prompted values are typed in by hand, like passwords
+            sensitive: variable.sensitive || matches!(variable.var_type, VariableType::Prompt),
+        })
+        .collect();
+    propagate_sensitive(&mut inspected_variables)?;
+
+    // Checked before resolving, which may run commands
+    let selected_variable = match &name {
+        Some(name) => Some(
+            inspected_variables
+                .iter()
+                .find(|variable| &variable.name == name)
+                .with_context(|| {
+                    format!(
+                        "Variable {} is not defined in configuration {:?}",
+                        name, file
+                    )
+                })?,
+        ),
+        None => None,
+    };
+
+    let var_map = total_variables_list.to_map().with_context(|| {
+        format!(
+            "While trying to resolve the variables of configuration {:?}",
+            file
+        )
+    })?;
+
+    if let Some(variable) = selected_variable {
+        return print_variable_details(variable, &var_map, reveal);
+    }
+
+    let mut sorted_variables: Vec<&InspectedVariable> = inspected_variables.iter().collect();
+    sorted_variables.sort_by(|a, b| a.name.cmp(&b.name));
+
+    for variable in sorted_variables {
+        let value = match var_map.get(&variable.name) {
+            Some(_) if variable.sensitive && !reveal => REDACTED,
+            Some(value) => value.as_str(),
+            None => "",
+        };
+
+        println!(
+            "{} = {} {}",
+            paint(White.bold(), &variable.name),
+            value,
+            paint(Black.dimmed(), format!("[{}]", variable.var_type.name()))
+        );
+    }
+
+    Ok(())
+}
@@ -0,0 +1,105 @@
+//! Lists every tracked file of a typewriter system
+//! along with whether its source and destination exist
+
+use ansi_term::{
+    Color::{Green, Red, White, Yellow},
+    Style,
+};
+use log::info;
+use serde::Serialize;
+use std::path::PathBuf;
+
+use crate::{log::paint, parse_config::load_config};
+
+/// A tracked file as listed
+#[derive(Serialize, Debug)]
+struct ListEntry {
+    // Source file to read from
+    file: PathBuf,
+
+    // Destination location to write to
+    destination: PathBuf,
+
+    // Group of files the file belongs to
+    group: Option<String>,
+
+    // Explanation of why the file is managed
+    description: Option<String>,
+
+    // Whether the source and destination exist
+    source_exists: bool,
+    destination_exists: bool,
+}
+
+impl ListEntry {
+    fn style(self: &Self) -> Style {
+        if !self.source_exists {
+            Red.normal()
+        } else if !self.destination_exists {
+            Yellow.normal()
+        } else {
+            Green.normal()
+        }
+    }
+}
+
+/// Whether a file exists as shown in the table, since
+/// the colors of the rows aren't always shown
+fn existence(exists: bool) -> &'static str {
+    if exists { "yes" } else { "no" }
+}
+
+pub fn list_command(file: String, section: String, json: bool) -> anyhow::Result<()> {
+    // Variables aren't needed to list files, so they are never resolved
+    let (total_files_list, _, _) = load_config(&file, section, |_| Ok(()))?;
+
+    // Only the existence of the files is checked, never their content
+    let entries: Vec<ListEntry> = total_files_list
+        .iter()
+        .map(|file| ListEntry {
+            file: file.file.clone(),
+            destination: file.destination.clone(),
+            group: file.group.clone(),
+            description: file.description.clone(),
+            source_exists: file.file.exists(),
+            destination_exists: file.destination.exists(),
+        })
+        .collect();
+
+    if json {
+        println!("{}", serde_json::to_string_pretty(&entries)?);
+        return Ok(());
+    }
+
+    println!(
+        "{}",
+        paint(
+            White.bold(),
+            format!(
+                "{:<12} {:<11} {:<11} {:<50} {:<50} {}",
+                "GROUP", "SRC EXISTS", "DEST EXISTS", "FILE", "DESTINATION", "DESCRIPTION"
+            )
+        )
+    );
+
+    for entry in entries.iter() {
+        println!(
+            "{}",
+            paint(
+                entry.style(),
+                format!(
+                    "{:<12} {:<11} {:<11} {:<50} {:<50} {}",
+                    entry.group.as_deref().unwrap_or("-"),
+                    existence(entry.source_exists),
+                    existence(entry.destination_exists),
+                    entry.file.to_string_lossy(),
+                    entry.destination.to_string_lossy(),
+                    entry.description.as_deref().unwrap_or("-")
+                )
+            )
+        );
+    }
+
+    info!("{} files are tracked", entries.len());
+    Ok(())
+}
@@ -0,0 +1,83 @@
+//! Migrates the stored checksums of a typewriter
+//! system from one checkdiff strategy to another
+
+use ansi_term::Color::{Black, White};
+use anyhow::bail;
+use log::{info, warn};
+
+use crate::{
+    apply::{checkdiff::FileCheckDiffStrategy, metadata_dirs},
+    log::paint,
+    parse_config::load_config,
+};
+
+pub fn migrate_checkdiff_command(
+    file: String,
+    section: String,
+    from: String,
+    to: String,
+) -> anyhow::Result<()> {
+    let from_strategy = FileCheckDiffStrategy::from_name(&from)?;
+    let to_strategy = FileCheckDiffStrategy::from_name(&to)?;
+
+    if let FileCheckDiffStrategy::Disabled = to_strategy {
+        bail!("Cannot migrate checksums to the disabled checkdiff strategy, it stores none");
+    }
+
+    // Config is needed for the metadata directories
+    let (total_files_list, _, _) = load_config(&file, section, |_| Ok(()))?;
+
+    let mut num_migrated = 0;
+    for metadata_dir in metadata_dirs(&total_files_list) {
+        let mut checksum_entries =
+            FileCheckDiffStrategy::read_checksum_entries(std::slice::from_ref(&metadata_dir))?;
+
+        if checksum_entries.entries.is_empty() {
+            continue;
+        }
+
+        let stored_entries = std::mem::take(&mut checksum_entries.entries);
+        for (destination, stored_hash) in stored_entries {
+            if !destination.exists() {
+                warn!(
+                    "Dropping checksum of {:?} since the file no longer exists",
+                    destination
+                );
+                continue;
+            }
+
+            // Only carry over checksums which are still valid, a changed
+            // file would otherwise be trusted by the next apply.
+            if !matches!(from_strategy, FileCheckDiffStrategy::Disabled)
+                && !FileCheckDiffStrategy::file_matches_stored(&stored_hash, &destination)?
+            {
+                warn!(
+                    "Dropping checksum of {:?} since it was changed since the last apply",
+                    destination
+                );
+                continue;
+            }
+
+            let Some(new_hash) = to_strategy.hash_file(&destination)? else {
+                continue;
+            };
+
+            println!(
+                "[{}] {:?} {}",
+                paint(White.bold(), "MIGRATED"),
+                destination,
+                paint(Black.dimmed(), format!("[{} -> {}]", stored_hash, new_hash))
+            );
+            checksum_entries.entries.insert(destination, new_hash);
+            num_migrated += 1;
+        }
+
+        FileCheckDiffStrategy::write_checksum_entries(&metadata_dir, &checksum_entries)?;
+    }
+
+    info!(
+        "Migrated {} checksums from {} to {}",
+        num_migrated, from, to
+    );
+    Ok(())
+}
@@ -1,4 +1,13 @@
 //! Different commands in the typewriter system
 
 pub mod apply;
+pub mod clean;
+pub mod diff;
 pub mod init;
+pub mod inspect;
+pub mod list;
+pub mod migrate_checkdiff;
+pub mod rollback;
+pub mod show_patch;
+pub mod status;
+pub mod validate;
@@ -0,0 +1,127 @@
+//! Manually rolls back the files of a typewriter
+//! system to the temporary copies left behind
+//! by a previous apply
+
+use ansi_term::Color::{Black, White};
+use inquire::Confirm;
+use log::info;
+
+use crate::{
+    apply::{
+        checkdiff::CheckDiffApplying,
+        hooks::HookStrategy,
+        strategy::ApplyStrategy,
+        tempcopy::{get_temp_copy_path, remove_temp_copy, restore_from_temp_copy},
+    },
+    config::ROOT_CONFIG,
+    file::TrackedFile,
+    log::paint,
+    parse_config::load_config,
+};
+
+/// Questions the user whether or not to restore this specific
+/// file from its temporary copy based on the configuration
+fn restore_file_prompt(file: &TrackedFile) -> anyhow::Result<bool> {
+    if !ROOT_CONFIG.get_config().apply.confirm_apply {
+        return Ok(true);
+    }
+
+    Ok(Confirm::new(
+        format!(
+            "Restore {:?} from temporary copy {:?}? This will overwrite it.",
+            file.destination,
+            get_temp_copy_path(file)?
+        )
+        .as_str(),
+    )
+    .with_default(false)
+    .prompt()?)
+}
+
+pub fn rollback_command(file: String, section: String) -> anyhow::Result<()> {
+    // Parse configs and grab data flattened into a list
+    let (mut total_files_list, _, total_hooks_list) = load_config(&file, section, |_| Ok(()))?;
+    let config = ROOT_CONFIG.get_config();
+
+    // Show which files actually have a temporary copy to restore from
+    let mut num_restores = 0;
+    for file in total_files_list.iter() {
+        let tempcopy_path = get_temp_copy_path(file)?;
+        if !tempcopy_path.exists() {
+            continue;
+        }
+
+        println!(
+            "[{}] {:?} from {:?} {}",
+            paint(White.bold(), "RESTORE"),
+            file.destination,
+            tempcopy_path,
+            paint(Black.dimmed(), format!("[ref: {:?}]", file.src))
+        );
+        num_restores += 1;
+    }
+
+    // Nothing to restore case.
+    if num_restores < 1 {
+        info!("No temporary copies found to restore from, no operation.");
+        return Ok(());
+    }
+
+    // Every file is confirmed on its own, so
+    // there is no prompt for all of them first
+    info!(
+        "Found {} files to restore from temporary copies",
+        num_restores
+    );
+
+    // Restore every file the user confirms, creating
+    // destinations which no longer exist along the way
+    let mut restored_files = Vec::new();
+    for file in total_files_list.iter() {
+        if !get_temp_copy_path(file)?.exists() {
+            continue;
+        }
+
+        if !restore_file_prompt(file)? {
+            info!("Skipping restore of {:?}", file.destination);
+            continue;
+        }
+
+        restore_from_temp_copy(file)?;
+        restored_files.push(file);
+    }
+
+    info!(
+        "Restored {} files from temporary copies",
+        restored_files.len()
+    );
+
+    // Temporary copies are no longer needed once restored, backups
+    // in their own location are kept around same as an apply does.
+    if config.apply.cleanup_files {
+        for file in restored_files {
+            if file.backup_destination.is_none() {
+                remove_temp_copy(file)?;
+            }
+        }
+    }
+
+    // Variables have no rollback behaviour and resolving
+    // them could run commands, so they are left out here.
+    // Temporary copies were already restored above.
+    let hook_strategy = HookStrategy::new(total_hooks_list)?;
+    let checkdiff_strategy =
+        CheckDiffApplying::new(&config.apply.checkdiff_strategy, &hook_strategy);
+    let strategies: Vec<&dyn ApplyStrategy> = vec![
+        &config.apply.file_permission_strategy,
+        &checkdiff_strategy,
+        &hook_strategy,
+    ];
+
+    // Same as a failed apply, run in reverse order
+    for strategy in strategies.iter().rev() {
+        strategy.run_on_failure(&mut total_files_list)?;
+    }
+
+    Ok(())
+}
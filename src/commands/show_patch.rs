@@ -0,0 +1,55 @@
+//! Shows the patch of the changes made
+//! to files by the last successful apply
+
+use ansi_term::Color::{Cyan, Green, Red, White};
+use anyhow::Context;
+use log::info;
+use std::fs;
+
+use crate::{
+    apply::{metadata_dirs, tempcopy::get_patch_file_path},
+    log::paint,
+    parse_config::load_config,
+};
+
+pub fn show_patch_command(file: String, section: String) -> anyhow::Result<()> {
+    // Config is only needed for the metadata directories
+    let (files, _, _) = load_config(&file, section, |_| Ok(()))?;
+
+    let mut found_patch = false;
+    for metadata_dir in metadata_dirs(&files) {
+        let path = get_patch_file_path(&metadata_dir)?;
+        if !path.exists() {
+            continue;
+        }
+
+        let patch = fs::read_to_string(&path)
+            .with_context(|| format!("While trying to read patch of applied changes {:?}", path))?;
+
+        print_colored_patch(&patch);
+        found_patch = true;
+    }
+
+    if !found_patch {
+        info!("No patch of applied changes found, has an apply been run yet?");
+    }
+
+    Ok(())
+}
+
+/// Prints the unified diff patch coloured similar to git
+pub fn print_colored_patch(patch: &str) {
+    for line in patch.lines() {
+        if line.starts_with("+++") || line.starts_with("---") {
+            println!("{}", paint(White.bold(), line));
+        } else if line.starts_with('+') {
+            println!("{}", paint(Green.normal(), line));
+        } else if line.starts_with('-') {
+            println!("{}", paint(Red.normal(), line));
+        } else if line.starts_with("@@") {
+            println!("{}", paint(Cyan.normal(), line));
+        } else {
+            println!("{}", line);
+        }
+    }
+}
@@ -0,0 +1,139 @@
+//! Shows which of the tracked files of a typewriter
+//! system are out of sync with their destinations
+
+use ansi_term::{
+    Color::{Black, Green, Red, White, Yellow},
+    Style,
+};
+use log::info;
+use std::collections::HashMap;
+
+use crate::{
+    apply::{checkdiff::FileCheckDiffStrategy, metadata_dirs, variables::VariableApplying},
+    config::ROOT_CONFIG,
+    log::paint,
+    parse_config::load_config,
+};
+
+/// Sync status of a single tracked file
+enum FileStatus {
+    // Destination has the rendered content of the source
+    UpToDate,
+
+    // Destination was changed since the last apply
+    ModifiedDestination,
+
+    // Source was changed since the last apply
+    ModifiedSource,
+
+    // Destination doesn't exist yet
+    MissingDestination,
+
+    // Source doesn't exist
+    MissingSource,
+}
+
+impl FileStatus {
+    fn label(self: &Self) -> &'static str {
+        match self {
+            FileStatus::UpToDate => "UP_TO_DATE",
+            FileStatus::ModifiedDestination => "MODIFIED_DESTINATION",
+            FileStatus::ModifiedSource => "MODIFIED_SOURCE",
+            FileStatus::MissingDestination => "MISSING_DESTINATION",
+            FileStatus::MissingSource => "MISSING_SOURCE",
+        }
+    }
+
+    fn style(self: &Self) -> Style {
+        match self {
+            FileStatus::UpToDate => Green.bold(),
+            FileStatus::ModifiedSource => Yellow.bold(),
+            FileStatus::ModifiedDestination
+            | FileStatus::MissingDestination
+            | FileStatus::MissingSource => Red.bold(),
+        }
+    }
+}
+
+pub fn status_command(file: String, section: String) -> anyhow::Result<()> {
+    let (total_files_list, total_variables_list, _) = load_config(&file, section, |_| Ok(()))?;
+    let config = ROOT_CONFIG.get_config();
+
+    // Sources are rendered in memory the same way applying does
+    let var_map = if config.variables.preload_skip {
+        HashMap::new()
+    } else {
+        total_variables_list.to_map()?
+    };
+    let var_strategy = VariableApplying::new(config.variables.variable_strategy, var_map);
+
+    // Stored checksums are compared the way they were stored with,
+    // the content itself is always compared with xxhash.
+    let hash_strategy = FileCheckDiffStrategy::XXHashDiff;
+    let checksum_entries =
+        FileCheckDiffStrategy::read_checksum_entries(&metadata_dirs(&total_files_list))?;
+
+    println!(
+        "{}",
+        paint(
+            White.bold(),
+            format!("{:<20} {:<10} {}", "STATUS", "CHECKSUM", "DESTINATION")
+        )
+    );
+
+    let mut num_out_of_sync = 0;
+    for file in total_files_list.iter() {
+        let stored_hash = checksum_entries.entries.get(&file.destination);
+
+        let (status, checksum) = if !file.file.exists() {
+            (FileStatus::MissingSource, "-")
+        } else if !file.destination.exists() {
+            (FileStatus::MissingDestination, "-")
+        } else {
+            let rendered_hash = hash_strategy.hash_bytes(&var_strategy.render_file(file)?);
+            let destination_hash = hash_strategy.hash_file(&file.destination)?;
+
+            // Whether the destination still has the content of the last apply
+            let is_checksum_current = match stored_hash {
+                Some(stored_hash) => {
+                    FileCheckDiffStrategy::file_matches_stored(stored_hash, &file.destination)?
+                }
+                None => false,
+            };
+            let checksum = match stored_hash {
+                Some(_) if is_checksum_current => "current",
+                Some(_) => "stale",
+                None => "none",
+            };
+
+            let status = if rendered_hash == destination_hash {
+                FileStatus::UpToDate
+            } else if is_checksum_current {
+                FileStatus::ModifiedSource
+            } else {
+                FileStatus::ModifiedDestination
+            };
+
+            (status, checksum)
+        };
+
+        if !matches!(status, FileStatus::UpToDate) {
+            num_out_of_sync += 1;
+        }
+
+        println!(
+            "{} {:<10} {:?} {}",
+            paint(status.style(), format!("{:<20}", status.label())),
+            checksum,
+            file.destination,
+            paint(Black.dimmed(), format!("[ref: {:?}]", file.src))
+        );
+    }
+
+    info!(
+        "{} of {} files are out of sync with their destinations",
+        num_out_of_sync,
+        total_files_list.len()
+    );
+    Ok(())
+}
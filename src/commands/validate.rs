@@ -0,0 +1,191 @@
+//! Validates a typewriter configuration without applying
+//! anything, running no commands and modifying no files
+
+use ansi_term::{
+    Color::{Black, Green, Red, Yellow},
+    Style,
+};
+use anyhow::{anyhow, bail};
+use log::info;
+use std::{collections::HashMap, path::Path};
+
+use crate::{log::paint, parse_config::load_config};
+
+/// Outcome of a single check of the configuration
+enum CheckResult {
+    // Check succeeded
+    Pass,
+
+    // Check failed
+    Fail,
+
+    // Check was not evaluated
+    Skip,
+}
+
+impl CheckResult {
+    fn label(self: &Self) -> &'static str {
+        match self {
+            CheckResult::Pass => "PASS",
+            CheckResult::Fail => "FAIL",
+            CheckResult::Skip => "SKIP",
+        }
+    }
+
+    fn style(self: &Self) -> Style {
+        match self {
+            CheckResult::Pass => Green.bold(),
+            CheckResult::Fail => Red.bold(),
+            CheckResult::Skip => Yellow.bold(),
+        }
+    }
+}
+
+/// Counts of the results of every check ran
+#[derive(Default)]
+struct ValidateReport {
+    passed: usize,
+    failed: usize,
+    skipped: usize,
+}
+
+impl ValidateReport {
+    /// Prints the result of a check and counts it
+    fn record(self: &mut Self, result: CheckResult, message: String) {
+        match result {
+            CheckResult::Pass => self.passed += 1,
+            CheckResult::Fail => self.failed += 1,
+            CheckResult::Skip => self.skipped += 1,
+        }
+
+        println!("[{}] {}", paint(result.style(), result.label()), message);
+    }
+
+    /// Records a check which passes unless it has an error
+    fn record_result(self: &mut Self, result: anyhow::Result<()>, message: String) {
+        match result {
+            Ok(()) => self.record(CheckResult::Pass, message),
+            Err(e) => self.record(
+                CheckResult::Fail,
+                format!(
+                    "{} {}",
+                    message,
+                    paint(Black.dimmed(), format!("[{:#}]", e))
+                ),
+            ),
+        }
+    }
+}
+
+/// Whether a file could be written at the destination, checked against
+/// the closest existing directory since missing ones are created on apply
+fn destination_writable(destination: &Path) -> anyhow::Result<()> {
+    let Some(existing_dir) = destination
+        .ancestors()
+        .skip(1)
+        .find(|ancestor| ancestor.exists())
+    else {
+        bail!("No parent directory of the destination exists");
+    };
+
+    if !existing_dir.is_dir() {
+        bail!("{:?} is not a directory", existing_dir);
+    }
+
+    if existing_dir.metadata()?.permissions().readonly() {
+        bail!("Directory {:?} is not writable", existing_dir);
+    }
+
+    Ok(())
+}
+
+pub fn validate_command(file: String, section: String) -> anyhow::Result<()> {
+    let mut report = ValidateReport::default();
+
+    // Nothing else can be checked without a parsed configuration
+    let (total_files_list, total_variables_list, total_hooks_list) =
+        match load_config(&file, section, |_| Ok(())) {
+            Ok(data) => data,
+            Err(e) => {
+                report.record(
+                    CheckResult::Fail,
+                    format!("Configuration {:?} parses", file),
+                );
+                return Err(e);
+            }
+        };
+    report.record(
+        CheckResult::Pass,
+        format!("Configuration {:?} parses", file),
+    );
+
+    // Variables resolve without running commands or reading the environment
+    let num_variables = total_variables_list.len();
+    match total_variables_list.to_map_unevaluated() {
+        Ok((_, unevaluated)) => {
+            report.record(
+                CheckResult::Pass,
+                format!("{} variables resolve", num_variables),
+            );
+
+            for name in unevaluated {
+                report.record(
+                    CheckResult::Skip,
+                    format!("Variable {} (not evaluated in validate mode)", name),
+                );
+            }
+        }
+        Err(e) => report.record_result(Err(e), format!("{} variables resolve", num_variables)),
+    }
+
+    for hook in total_hooks_list.iter() {
+        report.record_result(
+            hook.parse_stage().map(|_| ()),
+            format!("Hook {:?} has stage {:?}", hook.command, hook.stage),
+        );
+    }
+
+    // Every destination should only be applied to once
+    let mut destinations: HashMap<_, &Path> = HashMap::new();
+    for file in total_files_list.iter() {
+        let source_exists = if file.file.exists() {
+            Ok(())
+        } else {
+            Err(anyhow!("Source file does not exist"))
+        };
+        report.record_result(source_exists, format!("Source {:?} exists", file.file));
+
+        report.record_result(
+            destination_writable(&file.destination),
+            format!("Destination {:?} is writable", file.destination),
+        );
+
+        let duplicate = match destinations.insert(&file.destination, &file.src) {
+            Some(other_src) => Err(anyhow!("Destination is also applied to by {:?}", other_src)),
+            None => Ok(()),
+        };
+        report.record_result(
+            duplicate,
+            format!(
+                "Destination {:?} is unique {}",
+                file.destination,
+                paint(Black.dimmed(), format!("[ref: {:?}]", file.src))
+            ),
+        );
+    }
+
+    info!(
+        "{} checks passed, {} failed, {} skipped",
+        report.passed, report.failed, report.skipped
+    );
+
+    if report.failed > 0 {
+        bail!(
+            "Configuration {:?} failed {} validation checks",
+            file,
+            report.failed
+        );
+    }
+
+    Ok(())
+}
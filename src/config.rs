@@ -1,11 +1,14 @@
 //! Configuration structs and helpers for typewriter
 
 use std::{
+    collections::HashMap,
     ops::{Deref, DerefMut},
+    path::PathBuf,
     sync::OnceLock,
 };
 
-use serde::Deserialize;
+use anyhow::{Context, bail};
+use serde::{Deserialize, Serialize, de::DeserializeOwned};
 
 /// Wrapper around oncelock config to help
 /// retrieving config options globally.
@@ -34,7 +37,7 @@ pub struct TypewriterConfigs(pub Vec<Typewriter>);
 ///
 /// config is not utilised outside of the root
 /// file referenced directly by commands.
-#[derive(Deserialize, Debug)]
+#[derive(Deserialize, Serialize, Debug, Default)]
 #[serde(deny_unknown_fields)]
 pub struct Typewriter {
     // Global typewriter configuration options.
@@ -61,6 +64,16 @@ pub struct Typewriter {
     // Commands that are executed globally
     #[serde(alias = "hook", alias = "command", default)]
     pub hooks: HookList,
+
+    // Metadata directory for the files declared in this
+    // config, overriding the apply_metadata_dir of the root
+    #[serde(default)]
+    pub metadata_dir: Option<PathBuf>,
+
+    // Variables substituted into the raw content of this
+    // config before it is parsed, so they can be used anywhere
+    #[serde(default)]
+    pub vars_prelude: VariableList,
 }
 
 /// Global typewriter configuration options.
@@ -68,7 +81,7 @@ pub struct Typewriter {
 /// Can only be used by the root typewriter
 /// configuration file referenced in commands
 /// in order to keep tracking configuration simple
-#[derive(Deserialize, Default, Debug)]
+#[derive(Deserialize, Serialize, Debug)]
 #[serde(deny_unknown_fields)]
 pub struct Config {
     // Configuration options relating to
@@ -90,6 +103,146 @@ pub struct Config {
     // for running commands
     #[serde(default)]
     pub hooks: HooksConfig,
+
+    // Maximum size of linked configuration files in bytes,
+    // the root configuration file always uses the default
+    // since it has to be read to know this.
+    #[serde(default = "default_max_config_file_size")]
+    pub max_config_file_size: u64,
+
+    // Treat warnings as errors, aborting
+    // rather than continuing past them
+    #[serde(default)]
+    pub strict_mode: bool,
+
+    // Named sets of overrides for this config
+    // which can be selected with --profile
+    #[serde(default)]
+    pub profiles: HashMap<String, PartialConfig>,
+}
+
+/// Overrides of the global typewriter configuration options
+/// for a profile, options which are set are merged over the
+/// ones in the base config (tables are merged deeply).
+#[derive(Deserialize, Serialize, Debug, Default)]
+#[serde(deny_unknown_fields)]
+pub struct PartialConfig {
+    // Overrides of [config.apply]
+    pub apply: Option<toml::Table>,
+
+    // Overrides of [config.variables]
+    pub variables: Option<toml::Table>,
+
+    // Overrides of [config.commands]
+    pub commands: Option<toml::Table>,
+
+    // Overrides of [config.hooks]
+    pub hooks: Option<toml::Table>,
+
+    // Overrides of max_config_file_size
+    pub max_config_file_size: Option<u64>,
+
+    // Overrides of strict_mode
+    pub strict_mode: Option<bool>,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            apply: Default::default(),
+            variables: Default::default(),
+            commands: Default::default(),
+            hooks: Default::default(),
+            max_config_file_size: default_max_config_file_size(),
+            strict_mode: Default::default(),
+            profiles: Default::default(),
+        }
+    }
+}
+
+impl Config {
+    /// Merges the overrides of the profile with
+    /// the provided name over this config
+    pub fn apply_profile(self: &mut Self, name: &str) -> anyhow::Result<()> {
+        let Some(profile) = self.profiles.remove(name) else {
+            bail!(
+                "Profile {} is not defined in the profiles of the root configuration file",
+                name
+            );
+        };
+
+        if let Some(apply) = &profile.apply {
+            self.apply = merge_table_over(&self.apply, apply).with_context(|| {
+                format!("While trying to apply apply options of profile {}", name)
+            })?;
+        }
+
+        if let Some(variables) = &profile.variables {
+            self.variables = merge_table_over(&self.variables, variables).with_context(|| {
+                format!(
+                    "While trying to apply variables options of profile {}",
+                    name
+                )
+            })?;
+        }
+
+        if let Some(commands) = &profile.commands {
+            self.commands = merge_table_over(&self.commands, commands).with_context(|| {
+                format!("While trying to apply commands options of profile {}", name)
+            })?;
+        }
+
+        if let Some(hooks) = &profile.hooks {
+            self.hooks = merge_table_over(&self.hooks, hooks).with_context(|| {
+                format!("While trying to apply hooks options of profile {}", name)
+            })?;
+        }
+
+        if let Some(max_config_file_size) = profile.max_config_file_size {
+            self.max_config_file_size = max_config_file_size;
+        }
+
+        if let Some(strict_mode) = profile.strict_mode {
+            self.strict_mode = strict_mode;
+        }
+
+        Ok(())
+    }
+}
+
+/// Deep merges the table of overrides over the
+/// options in base, returning the merged options
+fn merge_table_over<T: Serialize + DeserializeOwned>(
+    base: &T,
+    overrides: &toml::Table,
+) -> anyhow::Result<T> {
+    let mut merged = toml::Value::try_from(base)?;
+
+    if let toml::Value::Table(merged_table) = &mut merged {
+        deep_merge(merged_table, overrides);
+    }
+
+    Ok(merged.try_into()?)
+}
+
+/// Merges overrides into base, merging tables
+/// present in both rather than replacing them
+pub fn deep_merge(base: &mut toml::Table, overrides: &toml::Table) {
+    for (key, value) in overrides {
+        match (base.get_mut(key), value) {
+            (Some(toml::Value::Table(base_table)), toml::Value::Table(override_table)) => {
+                deep_merge(base_table, override_table)
+            }
+            _ => {
+                base.insert(key.clone(), value.clone());
+            }
+        }
+    }
+}
+
+/// Default maximum size of configuration files (10 MB)
+pub fn default_max_config_file_size() -> u64 {
+    10 * 1024 * 1024
 }
 
 impl Deref for TypewriterConfigs {
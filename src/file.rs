@@ -1,22 +1,27 @@
 //! Files managed under the typewriter system
 
 use std::{
+    collections::HashMap,
     ops::{Deref, DerefMut},
     path::PathBuf,
 };
 
-use anyhow::Context;
-use serde::Deserialize;
+use anyhow::{Context, bail};
+use serde::{Deserialize, Serialize};
+use walkdir::WalkDir;
 
-use crate::cleanpath::CleanPath;
+use crate::{cleanpath::CleanPath, log::typewriter_warn};
+
+/// Characters which make the file of a tracked file a glob pattern
+const GLOB_METACHARACTERS: [char; 3] = ['*', '?', '['];
 
 /// List of tracked files with extra methods to help.
-#[derive(Deserialize, Default, Debug)]
+#[derive(Deserialize, Serialize, Default, Debug)]
 pub struct TrackedFileList(pub Vec<TrackedFile>);
 
 /// File in typewriter config that should be tracked and updated
 /// appropriately on apply.
-#[derive(Deserialize, Debug)]
+#[derive(Deserialize, Serialize, Debug, Clone)]
 #[serde(deny_unknown_fields)]
 pub struct TrackedFile {
     // Source file to read from
@@ -30,6 +35,11 @@ pub struct TrackedFile {
     // Destination location to write to
     pub destination: PathBuf,
 
+    // File name to give the destination instead of
+    // the one in the destination path
+    #[serde(default)]
+    pub rename_destination: Option<String>,
+
     // Hooks that are executed before this file is applied
     #[serde(default)]
     pub pre_hook: Vec<String>,
@@ -42,9 +52,88 @@ pub struct TrackedFile {
     #[serde(default)]
     pub continue_on_hook_error: bool,
 
+    // Variable names which should be left untouched in this
+    // file, for files with their own conflicting syntax
+    #[serde(default)]
+    pub skip_variables: Vec<String>,
+
+    // Skip this file rather than erroring if its source
+    // doesn't exist, for machine-specific files
+    #[serde(default)]
+    pub optional: bool,
+
+    // Deep merge the source into the destination as TOML
+    // rather than replacing the destination entirely
+    #[serde(default)]
+    pub merge: bool,
+
+    // Encoding of the source file, decoded from it
+    // and encoded back to it when writing (default UTF-8)
+    #[serde(default)]
+    pub encoding: Option<String>,
+
+    // Human-readable explanation of why
+    // this file is managed
+    #[serde(default)]
+    pub description: Option<String>,
+
+    // Permission mode to set on the destination after
+    // applying, overriding the preserved one
+    #[serde(default)]
+    pub mode: Option<u32>,
+
+    // Keep the permission mode and ownership of the destination,
+    // or give new destinations the mode of the source
+    #[serde(default = "default_is_true")]
+    pub preserve_permissions: bool,
+
+    // Tags to select this file by with apply --tags
+    #[serde(default)]
+    pub tags: Vec<String>,
+
+    // Group of files this file belongs to, e.g
+    // a machine profile, for apply --only-group
+    #[serde(default)]
+    pub group: Option<String>,
+
+    // Path to back the destination up to before applying
+    // instead of the temporary copy in the metadata directory
+    #[serde(default)]
+    pub backup_destination: Option<PathBuf>,
+
+    // Path the destination is expected to be a symlink to,
+    // checked with link_verification to detect tampering
+    #[serde(default)]
+    pub link_target: Option<PathBuf>,
+
+    // Values of variables for this file only, taking
+    // precedence over the global variables of the same name
+    #[serde(default)]
+    pub variables: HashMap<String, String>,
+
+    // Error rather than warn if the file is a glob
+    // pattern which doesn't match any files
+    #[serde(default)]
+    pub require_match: bool,
+
+    // Track the file as a directory, applying every
+    // file in it to the same path in the destination
+    #[serde(default)]
+    pub recursive: bool,
+
     // Source configuration file for this tracked file
     #[serde(skip)]
     pub src: PathBuf,
+
+    // Metadata directory of the configuration file
+    // of this tracked file, if it overrides the root one
+    #[serde(skip)]
+    pub metadata_dir: Option<PathBuf>,
+
+    // Amount of variables substituted into this file
+    // while it was applied
+    #[serde(skip)]
+    pub substitution_count: usize,
 }
 
 fn default_is_true() -> bool {
@@ -63,11 +152,145 @@ impl TrackedFile {
 
         // Absolutize the joined file path for both fields.
         self.file = parent.join(&self.file).clean_path()?;
+        // Destinations ending in a separator name a directory
+        let destination_is_dir = self.destination.to_string_lossy().ends_with('/');
         self.destination = parent.join(&self.destination).clean_path()?;
+
+        // Replace the file name of the destination if renamed
+        if let Some(rename_destination) = &self.rename_destination {
+            self.destination = if destination_is_dir {
+                self.destination.join(rename_destination)
+            } else {
+                self.destination.with_file_name(rename_destination)
+            };
+        }
+
+        if let Some(backup_destination) = &self.backup_destination {
+            self.backup_destination = Some(parent.join(backup_destination).clean_path()?);
+        }
+
+        if let Some(link_target) = &self.link_target {
+            self.link_target = Some(parent.join(link_target).clean_path()?);
+        }
+
         self.src = file_path.clean_path()?;
 
         Ok(())
     }
+
+    /// Whether the file is a glob pattern matching multiple files
+    pub fn is_glob(self: &Self) -> bool {
+        self.file.to_string_lossy().contains(GLOB_METACHARACTERS)
+    }
+
+    /// Expands a tracked file with a glob pattern as its file into a tracked
+    /// file for every file it matches, with the destination as the directory
+    /// to place them in. Tracked files without a pattern are returned as is.
+    pub fn expand_glob(self: Self) -> anyhow::Result<Vec<TrackedFile>> {
+        if !self.is_glob() {
+            return Ok(vec![self]);
+        }
+
+        if self.rename_destination.is_some() {
+            bail!(
+                "File {:?} referenced in configuration file {:?} is a glob pattern, so it can't have a rename_destination",
+                self.file,
+                self.src
+            );
+        }
+
+        let pattern = self.file.to_string_lossy();
+        let matches = glob::glob(&pattern).with_context(|| {
+            format!(
+                "While trying to parse glob pattern {:?} referenced in configuration file {:?}",
+                self.file, self.src
+            )
+        })?;
+
+        let mut expanded = Vec::new();
+        for matched in matches {
+            let matched = matched.with_context(|| {
+                format!(
+                    "While trying to expand glob pattern {:?} referenced in configuration file {:?}",
+                    self.file, self.src
+                )
+            })?;
+
+            // Directories can only be applied recursively
+            if !matched.is_file() && !(self.recursive && matched.is_dir()) {
+                continue;
+            }
+
+            let Some(file_name) = matched.file_name() else {
+                continue;
+            };
+
+            let mut tracked_file = self.clone();
+            tracked_file.destination = self.destination.join(file_name);
+            tracked_file.file = matched;
+            expanded.push(tracked_file);
+        }
+
+        if expanded.is_empty() {
+            typewriter_warn!(
+                strict = self.require_match;
+                "Glob pattern {:?} referenced in configuration file {:?} doesn't match any files",
+                self.file,
+                self.src
+            );
+        }
+
+        Ok(expanded)
+    }
+
+    /// Expands a recursive tracked file into a tracked file for every file in
+    /// its directory, with the same path relative to the destination directory.
+    /// Tracked files which aren't recursive are returned as is.
+    pub fn expand_directory(self: Self) -> anyhow::Result<Vec<TrackedFile>> {
+        if !self.recursive {
+            return Ok(vec![self]);
+        }
+
+        if !self.file.is_dir() {
+            bail!(
+                "File {:?} referenced in configuration file {:?} is recursive but not a directory",
+                self.file,
+                self.src
+            );
+        }
+
+        if self.rename_destination.is_some() {
+            bail!(
+                "Directory {:?} referenced in configuration file {:?} is recursive, so it can't have a rename_destination",
+                self.file,
+                self.src
+            );
+        }
+
+        let mut expanded = Vec::new();
+        for entry in WalkDir::new(&self.file).sort_by_file_name() {
+            let entry = entry.with_context(|| {
+                format!(
+                    "While trying to walk directory {:?} referenced in configuration file {:?}",
+                    self.file, self.src
+                )
+            })?;
+
+            if !entry.file_type().is_file() {
+                continue;
+            }
+
+            let relative_path = entry.path().strip_prefix(&self.file)?;
+
+            let mut tracked_file = self.clone();
+            tracked_file.destination = self.destination.join(relative_path);
+            tracked_file.file = entry.into_path();
+            tracked_file.recursive = false;
+            expanded.push(tracked_file);
+        }
+
+        Ok(expanded)
+    }
 }
 
 impl Deref for TrackedFileList {
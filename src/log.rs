@@ -3,7 +3,48 @@ use env_logger::{
     Env,
     fmt::style::{AnsiColor, Color, Style},
 };
-use std::io::Write;
+use std::{
+    borrow::Cow,
+    io::{IsTerminal, Write},
+    sync::OnceLock,
+};
+
+use crate::args::ColorMode;
+
+// When output should be coloured, set once
+// from the arguments before logging is setup
+static COLOR_MODE: OnceLock<ColorMode> = OnceLock::new();
+
+/// Sets when output should be coloured,
+/// only the first call has any effect.
+pub fn set_color_mode(mode: ColorMode) {
+    let _ = COLOR_MODE.set(mode);
+}
+
+/// Whether or not output should be coloured
+pub fn color_enabled() -> bool {
+    match COLOR_MODE.get().unwrap_or(&ColorMode::Auto) {
+        ColorMode::Always => true,
+        ColorMode::Never => false,
+        ColorMode::Auto => {
+            let no_color = std::env::var_os("NO_COLOR").is_some_and(|value| !value.is_empty());
+            !no_color && std::io::stdout().is_terminal()
+        }
+    }
+}
+
+/// Paints the text with the style, or leaves it
+/// as plain text if colour is disabled.
+pub fn paint<'a>(
+    style: ansi_term::Style,
+    text: impl Into<Cow<'a, str>>,
+) -> ansi_term::ANSIString<'a> {
+    if color_enabled() {
+        style.paint(text)
+    } else {
+        ansi_term::Style::new().paint(text)
+    }
+}
 
 pub fn setup_logging() {
     env_logger::Builder::from_env(Env::default().default_filter_or("debug"))
@@ -17,8 +58,14 @@ pub fn setup_logging() {
             }));
 
             // Level for the level text which should pop out
-            let level_style = Style::new().fg_color(level_color).bold();
-            let msg_style = Style::new().fg_color(level_color);
+            let (level_style, msg_style) = if color_enabled() {
+                (
+                    Style::new().fg_color(level_color).bold(),
+                    Style::new().fg_color(level_color),
+                )
+            } else {
+                (Style::new(), Style::new())
+            };
 
             writeln!(
                 buf,
@@ -29,3 +76,26 @@ pub fn setup_logging() {
         })
         .init();
 }
+
+/// Logs a warning, or returns an error with it
+/// instead if strict_mode is enabled in the config.
+///
+/// The strict mode can also be given directly with
+/// ``strict = ...;`` for before the config is loaded.
+macro_rules! typewriter_warn {
+    (strict = $strict:expr; $($arg:tt)+) => {
+        if $strict {
+            ::anyhow::bail!($($arg)+);
+        } else {
+            ::log::warn!($($arg)+);
+        }
+    };
+    ($($arg:tt)+) => {
+        $crate::log::typewriter_warn!(
+            strict = $crate::config::ROOT_CONFIG.get_config().strict_mode;
+            $($arg)+
+        )
+    };
+}
+
+pub(crate) use typewriter_warn;
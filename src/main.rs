@@ -1,6 +1,10 @@
 use ::log::{debug, error};
 
-use crate::{commands::init, log::setup_logging};
+use crate::{
+    args::ColorMode,
+    commands::init,
+    log::{set_color_mode, setup_logging},
+};
 
 // Argument parsing from cli
 mod args;
@@ -29,20 +33,62 @@ mod log;
 mod apply;
 
 fn main() {
-    setup_logging();
-
     // Parse arguments from CLI
     let args = args::parse_args();
+
+    // --no-color is a shorthand for --color never
+    set_color_mode(if args.no_color {
+        ColorMode::Never
+    } else {
+        args.color
+    });
+
+    setup_logging();
     debug!("typewriter running command: {}", args.command);
 
     // Run correct command handler.
     let command_result = match args.command {
         args::Commands::Init { file } => init::init_command(file),
-        args::Commands::Apply { file, section } => commands::apply::apply_command(file, section),
+        args::Commands::Apply(apply_args) => commands::apply::apply_command(apply_args),
+        args::Commands::Rollback { file, section } => {
+            commands::rollback::rollback_command(file, section)
+        }
+        args::Commands::ShowPatch { file, section } => {
+            commands::show_patch::show_patch_command(file, section)
+        }
+        args::Commands::MigrateCheckdiff {
+            file,
+            from,
+            to,
+            section,
+        } => commands::migrate_checkdiff::migrate_checkdiff_command(file, section, from, to),
+        args::Commands::Status { file, section } => commands::status::status_command(file, section),
+        args::Commands::Diff { file, section } => commands::diff::diff_command(file, section),
+        args::Commands::Validate { file, section } => {
+            commands::validate::validate_command(file, section)
+        }
+        args::Commands::List {
+            file,
+            section,
+            json,
+        } => commands::list::list_command(file, section, json),
+        args::Commands::Inspect {
+            file,
+            section,
+            name,
+            reveal,
+        } => commands::inspect::inspect_command(file, section, name, reveal),
+        args::Commands::Clean {
+            file,
+            section,
+            only_backups,
+            only_checkdiff,
+        } => commands::clean::clean_command(file, section, only_backups, only_checkdiff),
     };
 
     // Use error logger to print error..
-    let _ = command_result.inspect_err(|err| {
+    if let Err(err) = command_result {
         error!("{:?}", err);
-    });
+        std::process::exit(1);
+    }
 }
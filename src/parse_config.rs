@@ -1,15 +1,22 @@
 //! Parsing configuration file for typewriter
 
 use anyhow::{Context, bail};
-use log::warn;
-use serde::Deserialize;
+use log::info;
+use serde::{Deserialize, Serialize};
 use std::{
     collections::{HashMap, VecDeque},
     fs,
     path::PathBuf,
 };
 
-use crate::{cleanpath::CleanPath, config::*};
+use crate::{
+    apply::hooks::HookList,
+    cleanpath::CleanPath,
+    config::*,
+    file::TrackedFileList,
+    log::typewriter_warn,
+    vars::{VariableList, substitute_prelude},
+};
 
 /// Links to other typewriter configuration files
 ///
@@ -17,12 +24,20 @@ use crate::{cleanpath::CleanPath, config::*};
 /// to "include" it into the overall configuration
 /// in order to have better modularity/cleaner file structure
 /// for the system configuration
-#[derive(Deserialize, Debug)]
+#[derive(Deserialize, Serialize, Debug)]
 #[serde(deny_unknown_fields)]
 pub struct ConfigLink {
     file: PathBuf,
 }
 
+/// Only the vars_prelude of a configuration file,
+/// parsed before the rest of the config is.
+#[derive(Deserialize, Default)]
+struct VarsPrelude {
+    #[serde(default)]
+    vars_prelude: VariableList,
+}
+
 /// Is this link to another file (from origin_file) valid?
 fn validate_link(file_path: &PathBuf, origin_file: &PathBuf) -> anyhow::Result<()> {
     // Check if path exists, else error.
@@ -38,7 +53,30 @@ fn validate_link(file_path: &PathBuf, origin_file: &PathBuf) -> anyhow::Result<(
 }
 
 /// Parses an individual configuration file
-fn parse_single_config(file_path: &PathBuf, section: &String) -> anyhow::Result<Typewriter> {
+fn parse_single_config(
+    file_path: &PathBuf,
+    section: &String,
+    max_config_file_size: u64,
+) -> anyhow::Result<Typewriter> {
+    // Check the file isn't too large before reading it in
+    let file_size = fs::metadata(&file_path)
+        .with_context(|| {
+            format!(
+                "While trying to get size of configuration file {:?}",
+                file_path
+            )
+        })?
+        .len();
+
+    if file_size > max_config_file_size {
+        bail!(
+            "Configuration file {:?} is {} bytes, exceeding the max_config_file_size of {} bytes",
+            file_path,
+            file_size,
+            max_config_file_size
+        );
+    }
+
     // Read in content and try parse using toml
     let file_content = fs::read_to_string(&file_path)
         .with_context(|| format!("While trying to read configuration file {:?}", file_path))?;
@@ -50,8 +88,31 @@ fn parse_single_config(file_path: &PathBuf, section: &String) -> anyhow::Result<
                 format!("While trying to parse configuration file through quill scope extraction")
             })?;
 
-    let mut config: Typewriter = toml::from_str(&file_content)
-        .with_context(|| format!("While trying to parse configuration file {:?}", file_path))?;
+    // Substitute the prelude variables into the raw config first
+    let mut prelude: VarsPrelude = toml::from_str(&file_content).with_context(|| {
+        format!(
+            "While trying to parse vars_prelude of configuration file {:?}",
+            file_path
+        )
+    })?;
+
+    let mut config: Typewriter = if prelude.vars_prelude.is_empty() {
+        toml::from_str(&file_content)
+            .with_context(|| format!("While trying to parse configuration file {:?}", file_path))?
+    } else {
+        prelude
+            .vars_prelude
+            .iter_mut()
+            .try_for_each(|variable| variable.add_typewriter_dir(file_path))?;
+
+        let mut config_table: toml::Table = toml::from_str(&file_content)
+            .with_context(|| format!("While trying to parse configuration file {:?}", file_path))?;
+        substitute_prelude(&mut config_table, &prelude.vars_prelude.resolve_prelude()?)?;
+
+        toml::Value::Table(config_table)
+            .try_into()
+            .with_context(|| format!("While trying to parse configuration file {:?}", file_path))?
+    };
 
     // Add dir to the config path for file.
     config
@@ -59,6 +120,41 @@ fn parse_single_config(file_path: &PathBuf, section: &String) -> anyhow::Result<
         .iter_mut()
         .try_for_each(|tracked_file| tracked_file.add_typewriter_dir(file_path))?;
 
+    // Replace glob patterns and recursive directories with every file in them,
+    // leaving out optional files without a source on this machine entirely
+    let mut expanded_files = TrackedFileList::default();
+    for tracked_file in std::mem::take(&mut config.files.0) {
+        for tracked_file in tracked_file.expand_glob()? {
+            if tracked_file.optional && !tracked_file.file.exists() {
+                info!(
+                    "Skipping optional file {:?} — source not found",
+                    tracked_file.file
+                );
+                continue;
+            }
+
+            expanded_files.extend(tracked_file.expand_directory()?);
+        }
+    }
+    config.files = expanded_files;
+
+    // Files and variables use the metadata directory of this config if it has one
+    if let Some(metadata_dir) = &config.metadata_dir {
+        let metadata_dir = file_path
+            .parent()
+            .context("Configuration file has no parent directory")?
+            .join(metadata_dir)
+            .clean_path()?;
+
+        for tracked_file in config.files.iter_mut() {
+            tracked_file.metadata_dir = Some(metadata_dir.clone());
+        }
+
+        for variable in config.variables.iter_mut() {
+            variable.metadata_dir = Some(metadata_dir.clone());
+        }
+    }
+
     // Add dir to variable for debug info.
     config
         .variables
@@ -126,6 +222,11 @@ pub fn parse_config(
     let mut unprocessed_configs: VecDeque<PathBuf> = VecDeque::new();
     unprocessed_configs.push_back(file_path.clone());
 
+    // Size limit of config files, the limit set by the root
+    // is only known after it is parsed so it uses the default
+    let mut max_config_file_size = default_max_config_file_size();
+    let mut strict_mode = false;
+
     // Go over all unprocessed configs
     while let Some(current_path) = unprocessed_configs.pop_front() {
         // Already processed, skip
@@ -134,14 +235,23 @@ pub fn parse_config(
         }
 
         // Process this config, add its other configs to the unproc list
-        let config = parse_single_config(&current_path, &section)?;
+        let config = parse_single_config(&current_path, &section, max_config_file_size)?;
+
+        // Use the limit of the root for all linked configs
+        if current_path == file_path {
+            if let Some(root_config) = &config.config {
+                max_config_file_size = root_config.max_config_file_size;
+                strict_mode = root_config.strict_mode;
+            }
+        }
 
         // Warn about unsued config
         if !(current_path == file_path) && config.config.is_some() {
-            warn!(
+            typewriter_warn!(
+                strict = strict_mode;
                 "Unused global config in {:?}, since it is not the root file",
                 current_path
-            )
+            );
         }
 
         // Process all of the linked files and add them to unprocessed_configs.
@@ -160,3 +270,53 @@ pub fn parse_config(
         config_map.into_values().collect(),
     ))
 }
+
+/// Parses the root configuration file and all of its links,
+/// sets the global config from the root file (after letting
+/// modify_config change it) and returns all of the data
+/// from every config flattened into lists.
+pub fn load_config(
+    file: &str,
+    section: String,
+    modify_config: impl FnOnce(&mut Config) -> anyhow::Result<()>,
+) -> anyhow::Result<(TrackedFileList, VariableList, HookList)> {
+    // Validate file path
+    let path = file.clean_path()?;
+
+    // Parse configs to config structs.
+    let (mut root, configs) = parse_config(path, section)?;
+
+    // Fill in global root config from root
+    let mut global_config = root.config.take().unwrap_or_default();
+    modify_config(&mut global_config)?;
+    ROOT_CONFIG.set_config(global_config);
+
+    Ok(flatten_all_data(root, configs))
+}
+
+/// Parses the root configuration file and all of its links
+/// returning all of the data from every config flattened into
+/// lists, without touching the global config.
+pub fn parse_config_data(
+    file: &str,
+    section: String,
+) -> anyhow::Result<(TrackedFileList, VariableList, HookList)> {
+    let path = file.clean_path()?;
+    let (root, configs) = parse_config(path, section)?;
+
+    Ok(flatten_all_data(root, configs))
+}
+
+/// Grabs the data of the root and every linked config flattened into lists
+fn flatten_all_data(
+    root: Typewriter,
+    configs: TypewriterConfigs,
+) -> (TrackedFileList, VariableList, HookList) {
+    let (mut total_files_list, mut total_variables_list, mut total_hooks_list) =
+        configs.flatten_data();
+    total_files_list.extend(root.files.0.into_iter());
+    total_variables_list.extend(root.variables.0.into_iter());
+    total_hooks_list.extend(root.hooks.0.into_iter());
+
+    (total_files_list, total_variables_list, total_hooks_list)
+}
@@ -2,29 +2,31 @@
 
 use std::{
     collections::{HashMap, HashSet},
-    env,
+    env, fs,
     ops::{Deref, DerefMut},
     path::PathBuf,
 };
 
 use anyhow::{Context, bail};
+use inquire::Password;
+use log::warn;
 use regex::Regex;
-use serde::{Deserialize, de};
+use serde::{Deserialize, Serialize, de};
 
 use crate::{
-    apply::variables::VariableApplyingStrategy,
+    apply::{checkdiff::FileCheckDiffStrategy, variables::VariableApplyingStrategy},
     cleanpath::CleanPath,
     command::{CommandContext, execute_command},
     config::ROOT_CONFIG,
 };
 
 /// Helper list for interfacing with a list of variables
-#[derive(Deserialize, Debug, Default)]
+#[derive(Deserialize, Serialize, Debug, Default)]
 pub struct VariableList(pub Vec<Variable>);
 
 /// Global variable related configuration options
 /// (or preprocessor)
-#[derive(Deserialize, Debug)]
+#[derive(Deserialize, Serialize, Debug)]
 #[serde(deny_unknown_fields)]
 pub struct VariableConfig {
     // Variable format string to look for in the
@@ -41,18 +43,33 @@ pub struct VariableConfig {
     // Strategy to use for variable pre processing
     #[serde(default)]
     pub variable_strategy: VariableApplyingStrategy,
+
+    // Check every variable referenced in files is defined
+    // before applying, undefined ones are left as they are
+    #[serde(default = "default_validate_variables")]
+    pub validate_variables: bool,
+
+    // Skip resolving variables entirely, for configs
+    // which have none and don't use variables
+    #[serde(default)]
+    pub preload_skip: bool,
 }
 
 /// An individual "variable" which can be inserted
 /// by the preprocessor of typewriter into config
 /// files
-#[derive(Deserialize, Debug)]
+#[derive(Deserialize, Serialize, Debug)]
 #[serde(deny_unknown_fields)]
 pub struct Variable {
     // Source file that contains this variable
     #[serde(skip)]
     pub src: PathBuf,
 
+    // Metadata directory of the configuration
+    // file this variable is defined in, if it has one
+    #[serde(skip)]
+    pub metadata_dir: Option<PathBuf>,
+
     // Name of this variable, this should be unique
     // Non-unique variables in a system will cause Error
     // and abort the operation.
@@ -67,11 +84,51 @@ pub struct Variable {
     // Value which will be inserted in preprocess-time
     // into config files.
     pub value: String,
+
+    // Command to post-process the value with, the value
+    // is given to it on stdin and its output is used instead.
+    #[serde(default)]
+    pub transform: Option<String>,
+
+    // Set the resolved value as an environment variable of typewriter
+    // so it is available to hooks and other commands
+    #[serde(default)]
+    pub export: bool,
+
+    // Keep the full output of command variables rather
+    // than stripping its trailing newline
+    #[serde(default)]
+    pub multiline: bool,
+
+    // Maximum length in characters of the resolved value, to catch
+    // commands accidentally producing huge outputs
+    #[serde(default)]
+    pub max_length: Option<usize>,
+
+    // Human-readable explanation of what
+    // this variable is for
+    #[serde(default)]
+    pub description: Option<String>,
+
+    // Whether the value holds a secret, so
+    // it is redacted when variables are shown
+    #[serde(default)]
+    pub sensitive: bool,
+
+    // Treat the value of a literal variable as the path of
+    // an applied file and use its stored checksum instead
+    #[serde(default)]
+    pub from_file_checksum: bool,
+
+    // Variables which have to be resolved before this one,
+    // on top of the ones referenced in its value
+    #[serde(default)]
+    pub depends_on: Vec<String>,
 }
 
 /// Types of variables supported
 /// in typewriter
-#[derive(Deserialize, Debug, Clone, Copy)]
+#[derive(Deserialize, Serialize, Debug, Clone, Copy)]
 pub enum VariableType {
     // Directly insert the value
     // as a string in all references to the variable
@@ -87,6 +144,26 @@ pub enum VariableType {
     // the environment variables value in all references to the variable.
     #[serde(rename = "environment")]
     Environment,
+
+    // Read a value out of a JSON file, the value being
+    // <filepath>:<dot.separated.key>
+    #[serde(rename = "json_query")]
+    JsonQuery,
+
+    // Read a value out of a TOML file, the value being
+    // <filepath>:<dot.separated.key>
+    #[serde(rename = "toml_query")]
+    TomlQuery,
+
+    // Read the entire content of the file at the path
+    // in the value and insert it in the references
+    #[serde(rename = "file")]
+    File,
+
+    // Ask the user for the value when applying without echoing
+    // it, the value being the message to prompt with
+    #[serde(rename = "prompt")]
+    Prompt,
 }
 
 impl Default for VariableType {
@@ -95,11 +172,28 @@ impl Default for VariableType {
     }
 }
 
+impl VariableType {
+    /// Name of the type in the configuration, e.g "command"
+    pub fn name(self: &Self) -> &'static str {
+        match self {
+            VariableType::Literal => "literal",
+            VariableType::Command => "command",
+            VariableType::Environment => "environment",
+            VariableType::JsonQuery => "json_query",
+            VariableType::TomlQuery => "toml_query",
+            VariableType::File => "file",
+            VariableType::Prompt => "prompt",
+        }
+    }
+}
+
 impl Default for VariableConfig {
     fn default() -> Self {
         Self {
             variable_format: default_variable_format(),
             variable_strategy: Default::default(),
+            validate_variables: default_validate_variables(),
+            preload_skip: Default::default(),
         }
     }
 }
@@ -109,6 +203,10 @@ fn default_variable_format() -> String {
     String::from("$TYPEWRITER{{variable}}")
 }
 
+fn default_validate_variables() -> bool {
+    true
+}
+
 /// Special deserialize for variable names to ensure
 /// they're correct.
 fn deserialize_variable_name<'de, D>(deserializer: D) -> Result<String, D::Error>
@@ -164,18 +262,19 @@ impl FromIterator<Variable> for VariableList {
     }
 }
 
-/// Executes a command using the ROOT_CONF variable shell
-/// configs and waits for the stdin and returns it
-
 /// Executes a command using the ROOT_CONF variable shell
 /// configs and waits for the stdout and returns it
+///
+/// stdin_data is piped into the command if supplied.
 fn execute_command_conf_shell(
     var_name: &String,
     var_src: &PathBuf,
     command: &String,
+    stdin_data: Option<String>,
 ) -> anyhow::Result<String> {
     // Set context of this command's execution
     let mut context = CommandContext::default();
+    context.stdin_data = stdin_data;
     context.description = Some(format!(
         "for variable {} defined in configuration file {:?}",
         var_name, var_src
@@ -192,7 +291,7 @@ fn execute_command_conf_shell(
 
 /// Extracts variable references from a string based on the variable format
 /// Returns a vector of variable names found
-fn extract_variable_references(text: &str) -> anyhow::Result<Vec<String>> {
+pub fn extract_variable_references(text: &str) -> anyhow::Result<Vec<String>> {
     let var_conf = &ROOT_CONFIG.get_config().variables;
     let format = &var_conf.variable_format;
 
@@ -214,7 +313,7 @@ fn extract_variable_references(text: &str) -> anyhow::Result<Vec<String>> {
 
 /// Resolves variable references within a value string
 /// Returns the resolved string with all variable references replaced
-fn resolve_variable_references(value: &str, resolved_vars: &HashMap<String, String>) -> String {
+pub fn resolve_variable_references(value: &str, resolved_vars: &HashMap<String, String>) -> String {
     let var_conf = &ROOT_CONFIG.get_config().variables;
     let format = &var_conf.variable_format;
 
@@ -229,21 +328,254 @@ fn resolve_variable_references(value: &str, resolved_vars: &HashMap<String, Stri
     result
 }
 
+/// Splits the value of a query variable into the path of the
+/// file (relative to the configuration file) and the key path.
+fn split_query_value<'a>(
+    var_name: &String,
+    var_src: &PathBuf,
+    var_value: &'a str,
+) -> anyhow::Result<(PathBuf, &'a str)> {
+    let Some((file_path, key_path)) = var_value.rsplit_once(':') else {
+        bail!(
+            "Value {} of variable {} defined in configuration file {:?} should be <filepath>:<dot.separated.key>",
+            var_value,
+            var_name,
+            var_src
+        );
+    };
+
+    let parent = var_src
+        .parent()
+        .context("Configuration file has no parent directory")?;
+
+    Ok((parent.join(file_path).clean_path()?, key_path))
+}
+
+/// Reads the checksum stored for the file at the path by the last
+/// apply which wrote to it, from the metadata directory of the
+/// configuration file of the variable and the apply_metadata_dir
+fn read_file_checksum(
+    var_name: &String,
+    var_src: &PathBuf,
+    var_metadata_dir: Option<&PathBuf>,
+    var_value: &str,
+) -> anyhow::Result<String> {
+    let file_path = var_src
+        .parent()
+        .context("Configuration file has no parent directory")?
+        .join(var_value)
+        .clean_path()?;
+
+    // Later directories win, so the own metadata directory is read last
+    let mut metadata_dirs = vec![ROOT_CONFIG.get_config().apply.apply_metadata_dir.clone()];
+    metadata_dirs.extend(var_metadata_dir.cloned());
+    let checksum_entries = FileCheckDiffStrategy::read_checksum_entries(&metadata_dirs)?;
+
+    checksum_entries
+        .entries
+        .get(&file_path)
+        .cloned()
+        .with_context(|| {
+            format!(
+                "No stored checksum for file {:?} used by variable {} defined in configuration file {:?}, has it been applied yet?",
+                file_path, var_name, var_src
+            )
+        })
+}
+
+/// Reads the value at the key path out of a JSON file
+fn query_json_file(
+    var_name: &String,
+    var_src: &PathBuf,
+    var_value: &str,
+) -> anyhow::Result<String> {
+    let (file_path, key_path) = split_query_value(var_name, var_src, var_value)?;
+
+    let file_content = fs::read_to_string(&file_path).with_context(|| {
+        format!(
+            "While trying to read JSON file {:?} for variable {} defined in configuration file {:?}",
+            file_path, var_name, var_src
+        )
+    })?;
+
+    let json: serde_json::Value = serde_json::from_str(&file_content).with_context(|| {
+        format!(
+            "While trying to parse JSON file {:?} for variable {} defined in configuration file {:?}",
+            file_path, var_name, var_src
+        )
+    })?;
+
+    // Dot separated keys to a JSON pointer, escaping as needed
+    let pointer: String = key_path
+        .split('.')
+        .map(|key| format!("/{}", key.replace('~', "~0").replace('/', "~1")))
+        .collect();
+
+    match json.pointer(&pointer) {
+        Some(serde_json::Value::String(value)) => Ok(value.clone()),
+        Some(value) => Ok(value.to_string()),
+        None => bail!(
+            "Key {} does not exist in JSON file {:?} for variable {} defined in configuration file {:?}",
+            key_path,
+            file_path,
+            var_name,
+            var_src
+        ),
+    }
+}
+
+/// Reads the value at the key path out of a TOML file
+fn query_toml_file(
+    var_name: &String,
+    var_src: &PathBuf,
+    var_value: &str,
+) -> anyhow::Result<String> {
+    let (file_path, key_path) = split_query_value(var_name, var_src, var_value)?;
+
+    let file_content = fs::read_to_string(&file_path).with_context(|| {
+        format!(
+            "While trying to read TOML file {:?} for variable {} defined in configuration file {:?}",
+            file_path, var_name, var_src
+        )
+    })?;
+
+    let table: toml::Table = toml::from_str(&file_content).with_context(|| {
+        format!(
+            "While trying to parse TOML file {:?} for variable {} defined in configuration file {:?}",
+            file_path, var_name, var_src
+        )
+    })?;
+
+    // Walk down the keys, indexing arrays with numbers
+    let mut current = &toml::Value::Table(table);
+    for key in key_path.split('.') {
+        let next = match current {
+            toml::Value::Table(table) => table.get(key),
+            toml::Value::Array(array) => {
+                key.parse::<usize>().ok().and_then(|index| array.get(index))
+            }
+            _ => None,
+        };
+
+        let Some(next) = next else {
+            bail!(
+                "Key {} does not exist in TOML file {:?} for variable {} defined in configuration file {:?}",
+                key_path,
+                file_path,
+                var_name,
+                var_src
+            );
+        };
+        current = next;
+    }
+
+    match current {
+        toml::Value::String(value) => Ok(value.clone()),
+        value => Ok(value.to_string()),
+    }
+}
+
+/// Reads the entire content of the file at the path (relative to the
+/// configuration file), stripping trailing newlines unless multiline
+fn read_variable_file(
+    var_name: &String,
+    var_src: &PathBuf,
+    var_value: &str,
+    multiline: bool,
+) -> anyhow::Result<String> {
+    let file_path = var_src
+        .parent()
+        .context("Configuration file has no parent directory")?
+        .join(var_value)
+        .clean_path()?;
+
+    if !file_path.exists() {
+        bail!(
+            "File {:?} for variable {} defined in configuration file {:?} does not exist",
+            file_path,
+            var_name,
+            var_src
+        );
+    }
+
+    let content = fs::read_to_string(&file_path).with_context(|| {
+        format!(
+            "While trying to read file {:?} for variable {} defined in configuration file {:?}",
+            file_path, var_name, var_src
+        )
+    })?;
+
+    if multiline {
+        return Ok(content);
+    }
+
+    Ok(content.trim_end_matches(['\n', '\r']).to_string())
+}
+
+/// Asks the user for the value of the variable without echoing
+/// it, the description of the variable is shown as help if any
+fn prompt_variable_value(
+    var_name: &String,
+    var_src: &PathBuf,
+    message: &str,
+    description: Option<&str>,
+) -> anyhow::Result<String> {
+    // Nobody is there to answer without confirmations
+    if !ROOT_CONFIG.get_config().commands.confirm_shell_commands {
+        bail!(
+            "Variable {} defined in configuration file {:?} is a prompt variable, which cannot be used in non-interactive mode (confirm_shell_commands is false)",
+            var_name,
+            var_src
+        );
+    }
+
+    let mut prompt = Password::new(message).without_confirmation();
+    if let Some(description) = description {
+        prompt = prompt.with_help_message(description);
+    }
+
+    prompt.prompt().with_context(|| {
+        format!(
+            "While trying to prompt for the value of variable {} defined in configuration file {:?}",
+            var_name, var_src
+        )
+    })
+}
+
 /// Returns the string-to-insert value of this variable
 /// gotten from the type
 /// Name & Src fields are for debugging info for the user.
 fn get_true_value(
     var_name: &String,
     var_src: &PathBuf,
+    var_metadata_dir: Option<&PathBuf>,
     var_type: VariableType,
     var_value: String,
+    multiline: bool,
+    from_file_checksum: bool,
+    description: Option<&str>,
 ) -> anyhow::Result<String> {
     match var_type {
+        VariableType::Literal if from_file_checksum => {
+            read_file_checksum(var_name, var_src, var_metadata_dir, &var_value)
+        }
         VariableType::Literal => Ok(var_value),
-        VariableType::Command => execute_command_conf_shell(var_name, var_src, &var_value),
+        VariableType::Command => {
+            let output = execute_command_conf_shell(var_name, var_src, &var_value, None)?;
+
+            // Strip the trailing newline unless the full output is wanted
+            match output.strip_suffix('\n') {
+                Some(stripped) if !multiline => Ok(stripped.to_string()),
+                _ => Ok(output),
+            }
+        }
         VariableType::Environment => env::var(&var_value).with_context(|| {
             format!("While trying to get environment variable {} for variable {} defined in configuration file {:?}", var_value, var_name, var_src)
         }),
+        VariableType::JsonQuery => query_json_file(var_name, var_src, &var_value),
+        VariableType::TomlQuery => query_toml_file(var_name, var_src, &var_value),
+        VariableType::File => read_variable_file(var_name, var_src, &var_value, multiline),
+        VariableType::Prompt => prompt_variable_value(var_name, var_src, &var_value, description),
     }
 }
 
@@ -281,8 +613,8 @@ fn resolve_variable(
     // Extract references from the variable's value
     let references = extract_variable_references(&variable.value)?;
 
-    // Recursively resolve all dependencies first
-    for ref_name in &references {
+    // Recursively resolve all dependencies first, explicit ones included
+    for ref_name in references.iter().chain(variable.depends_on.iter()) {
         resolve_variable(ref_name, variables, resolved, resolving)?;
     }
 
@@ -293,10 +625,47 @@ fn resolve_variable(
     let final_value = get_true_value(
         &variable.name,
         &variable.src,
+        variable.metadata_dir.as_ref(),
         variable.var_type,
         resolved_value,
+        variable.multiline,
+        variable.from_file_checksum,
+        variable.description.as_deref(),
     )?;
 
+    // Guard against values which are far too long
+    if let Some(max_length) = variable.max_length {
+        let length = final_value.chars().count();
+        if length > max_length {
+            bail!(
+                "Variable '{}' resolved to {} chars, exceeding max_length of {} (defined in {:?})",
+                variable.name,
+                length,
+                max_length,
+                variable.src
+            );
+        }
+    }
+
+    // Post-process the value through the transform command
+    let final_value = match &variable.transform {
+        Some(transform) => {
+            let output = execute_command_conf_shell(
+                &variable.name,
+                &variable.src,
+                transform,
+                Some(final_value),
+            )?;
+
+            // Strip the trailing newline unless the full output is wanted
+            match output.strip_suffix('\n') {
+                Some(stripped) if !variable.multiline => stripped.to_string(),
+                _ => output,
+            }
+        }
+        None => final_value,
+    };
+
     // Remove from resolving set and add to resolved
     resolving.remove(var_name);
     resolved.insert(var_name.to_string(), final_value);
@@ -343,6 +712,167 @@ impl VariableList {
             resolve_variable(&var_name, &var_map, &mut resolved, &mut resolving)?;
         }
 
+        // Export variables to the environment for commands
+        for variable in var_map.values().filter(|variable| variable.export) {
+            // Setting the environment panics on these rather than erroring
+            let value = &resolved[&variable.name];
+            if variable.name.contains(['=', '\0']) || value.contains('\0') {
+                bail!(
+                    "Variable {} defined in configuration file {:?} cannot be exported, its name can't contain '=' or NUL characters and its value can't contain NUL characters",
+                    variable.name,
+                    variable.src
+                );
+            }
+
+            warn!(
+                "Exporting variable {} defined in configuration file {:?} to the process environment",
+                variable.name, variable.src
+            );
+
+            // SAFETY: No other threads are running while variables are resolved
+            unsafe { env::set_var(&variable.name, value) };
+        }
+
         Ok(resolved)
     }
+
+    /// Resolves the variables the same way as to_map without running any
+    /// commands, reading the environment or prompting, command, environment
+    /// and prompt variables resolve to an empty value and transforms are not ran.
+    /// Variables depending on those aren't evaluated either, since they would
+    /// be evaluated with the empty values (e.g querying the wrong file).
+    ///
+    /// Returns the map along with the names of the variables left unevaluated.
+    pub fn to_map_unevaluated(
+        self: Self,
+    ) -> anyhow::Result<(HashMap<String, String>, Vec<String>)> {
+        let mut unevaluated: Vec<String> = self
+            .iter()
+            .filter(|variable| {
+                matches!(
+                    variable.var_type,
+                    VariableType::Command | VariableType::Environment | VariableType::Prompt
+                )
+            })
+            .map(|variable| variable.name.clone())
+            .collect();
+
+        // Everything depending on an unevaluated variable, directly or not
+        let mut dependencies = Vec::new();
+        for variable in self.iter() {
+            let mut depends_on = extract_variable_references(&variable.value)?;
+            depends_on.extend(variable.depends_on.iter().cloned());
+            dependencies.push((&variable.name, depends_on));
+        }
+
+        let mut changed = true;
+        while changed {
+            changed = false;
+            for (name, depends_on) in dependencies.iter() {
+                if !unevaluated.contains(name)
+                    && depends_on
+                        .iter()
+                        .any(|dependency| unevaluated.contains(dependency))
+                {
+                    unevaluated.push(name.to_string());
+                    changed = true;
+                }
+            }
+        }
+
+        let variables: VariableList = self
+            .0
+            .into_iter()
+            .map(|mut variable| {
+                if unevaluated.contains(&variable.name) {
+                    variable.var_type = VariableType::Literal;
+                    variable.value = String::new();
+                }
+
+                variable.transform = None;
+                variable.export = false;
+                variable
+            })
+            .collect();
+
+        Ok((variables.to_map()?, unevaluated))
+    }
+
+    /// Resolves the variables of the vars_prelude of a configuration file,
+    /// these are resolved before the global config is known so only literal
+    /// and environment variables are supported.
+    pub fn resolve_prelude(self: &Self) -> anyhow::Result<HashMap<String, String>> {
+        let mut resolved = HashMap::new();
+
+        for variable in self.iter() {
+            let value = match variable.var_type {
+                VariableType::Literal => variable.value.clone(),
+                VariableType::Environment => env::var(&variable.value).with_context(|| {
+                    format!(
+                        "While trying to get environment variable {} for prelude variable {} defined in configuration file {:?}",
+                        variable.value, variable.name, variable.src
+                    )
+                })?,
+                _ => bail!(
+                    "Prelude variable {} defined in configuration file {:?} has to be a literal or environment variable",
+                    variable.name,
+                    variable.src
+                ),
+            };
+
+            resolved.insert(variable.name.clone(), value);
+        }
+
+        Ok(resolved)
+    }
+}
+
+/// Substitutes the prelude variables into every string of a parsed configuration
+/// file using the default variable format, leaving other variables as they are.
+///
+/// Substituting after parsing means values never have to be escaped for TOML.
+pub fn substitute_prelude(
+    config: &mut toml::Table,
+    prelude: &HashMap<String, String>,
+) -> anyhow::Result<()> {
+    let escaped = regex::escape(&default_variable_format());
+    let variable_regex = Regex::new(&escaped.replace("\\{variable\\}", "([^}]+)"))?;
+
+    for value in config.values_mut() {
+        substitute_prelude_value(value, &variable_regex, prelude);
+    }
+
+    Ok(())
+}
+
+/// Substitutes the prelude variables into the value if it
+/// is a string, or into every string nested in it otherwise.
+fn substitute_prelude_value(
+    value: &mut toml::Value,
+    variable_regex: &Regex,
+    prelude: &HashMap<String, String>,
+) {
+    match value {
+        toml::Value::String(string) => {
+            *string = variable_regex
+                .replace_all(string, |caps: &regex::Captures| {
+                    match prelude.get(&caps[1]) {
+                        Some(value) => value.clone(),
+                        None => caps[0].to_string(),
+                    }
+                })
+                .into_owned();
+        }
+        toml::Value::Array(array) => {
+            for value in array.iter_mut() {
+                substitute_prelude_value(value, variable_regex, prelude);
+            }
+        }
+        toml::Value::Table(table) => {
+            for value in table.values_mut() {
+                substitute_prelude_value(value, variable_regex, prelude);
+            }
+        }
+        _ => {}
+    }
 }
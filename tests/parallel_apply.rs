@@ -0,0 +1,70 @@
+//! Applying a large set of files with parallel_apply enabled
+
+use std::{fs, process::Command};
+
+/// Amount of synthetic files to apply
+const FILE_COUNT: usize = 100;
+
+#[test]
+fn parallel_apply_writes_every_file() {
+    let dir = tempfile::tempdir().unwrap();
+    let destination_dir = dir.path().join("destination");
+
+    let mut config = String::from(
+        r#"[config.apply]
+confirm_apply = false
+parallel_apply = true
+file_permission_strategy = "create_if_missing"
+checkdiff_strategy = "disabled"
+
+[config.commands]
+confirm_shell_commands = false
+
+[[var]]
+name = "greeting"
+value = "hello"
+"#,
+    );
+
+    for index in 0..FILE_COUNT {
+        let source = format!("source_{}.txt", index);
+        fs::write(
+            dir.path().join(&source),
+            format!("$TYPEWRITER{{greeting}} from file {}\n", index),
+        )
+        .unwrap();
+
+        config.push_str(&format!(
+            "\n[[file]]\nfile = \"{}\"\ndestination = \"destination/{}\"\n",
+            source, source
+        ));
+    }
+
+    let config_path = dir.path().join("typewriter.toml");
+    fs::write(&config_path, config).unwrap();
+
+    // Metadata directory is relative to the working directory
+    let output = Command::new(env!("CARGO_BIN_EXE_typewriter"))
+        .current_dir(dir.path())
+        .arg("apply")
+        .arg("--file")
+        .arg(&config_path)
+        .output()
+        .unwrap();
+
+    assert!(
+        output.status.success(),
+        "apply failed: {}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+
+    for index in 0..FILE_COUNT {
+        let destination = destination_dir.join(format!("source_{}.txt", index));
+        assert_eq!(
+            fs::read_to_string(&destination).unwrap(),
+            format!("hello from file {}\n", index),
+            "unexpected content in {:?}",
+            destination
+        );
+    }
+}